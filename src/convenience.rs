@@ -3,17 +3,16 @@ use crate::renderer::render;
 use crate::Error;
 use crate::Options;
 
-pub fn textile_to_html_with_options<'a>(
-    textile: &'a str,
-    options: Options,
-) -> Result<String, Error> {
-    let mut iterator = Parser::new(textile);
-    render(&mut iterator, &options)
+pub fn textile_to_html_with_options(textile: &str, options: Options) -> Result<String, Error> {
+    let mut iterator = Parser::new_with_options(textile, &options);
+    let document = iterator.document().clone();
+    render(&mut iterator, &options, &document)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::image_size::TestDir;
     use anyhow::Result;
 
     #[test]
@@ -24,4 +23,89 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn image_dimensions() -> Result<()> {
+        let dir = TestDir::new("image_dimensions")?;
+        let root = dir.path().to_path_buf();
+        std::fs::write(root.join("dot.gif"), b"GIF89a\x03\x00\x02\x00")?;
+
+        let options = Options {
+            document_root_directory: Some(root.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p><img alt=\"\" height=\"2\" src=\"/dot.gif\" width=\"3\" /></p>",
+            textile_to_html_with_options("!/dot.gif!", options)?
+        );
+
+        let options = Options {
+            document_root_directory: Some(root),
+            dimensionless_images: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p><img alt=\"\" src=\"/dot.gif\" /></p>",
+            textile_to_html_with_options("!/dot.gif!", options)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn restricted_mode() -> Result<()> {
+        let options = || Options {
+            restricted_mode: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p class=\"a\">&lt;b&gt;&#8220;x&#8221; &amp; <em id=\"e\">y</em>&lt;/b&gt;</p>",
+            textile_to_html_with_options(
+                "p(a){color:red}>. <b>\"x\" & _(#e){color:red}y_</b>",
+                options()
+            )?
+        );
+        assert_eq!(
+            "<p><a href=\"http://example.com/\" rel=\"nofollow\">ok</a> &#8220;bad&#8221;:javascript:alert(1) !/i.png!</p>",
+            textile_to_html_with_options(
+                "\"ok\":http://example.com/ \"bad\":javascript:alert(1) !/i.png!",
+                options()
+            )?
+        );
+        assert_eq!(
+            "&lt;div&gt;x&lt;/div&gt;",
+            textile_to_html_with_options("notextile. <div>x</div>", options())?
+        );
+        assert_eq!(
+            "<p><img alt=\"\" src=\"/i.png\" /></p>",
+            textile_to_html_with_options(
+                "!/i.png!",
+                Options {
+                    handle_images: Some(true),
+                    ..options()
+                }
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn url_prefixes() -> Result<()> {
+        let dir = TestDir::new("url_prefixes")?;
+        std::fs::write(dir.path().join("dot.gif"), b"GIF89a\x03\x00\x02\x00")?;
+
+        let options = Options {
+            document_root_directory: Some(dir.path().to_path_buf()),
+            image_prefix: Some("/img/".to_string()),
+            link_prefix: Some("/site/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p><a href=\"/site/about\">About</a> <a href=\"/top\">top</a> <a href=\"https://example.com/\">out</a> <img alt=\"\" height=\"2\" src=\"/img/dot.gif\" width=\"3\" /></p>",
+            textile_to_html_with_options(
+                "\"About\":about \"top\":/top \"out\":https://example.com/ !dot.gif!",
+                options
+            )?
+        );
+        Ok(())
+    }
 }
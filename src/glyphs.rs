@@ -0,0 +1,319 @@
+use crate::options::Symbol;
+use std::collections::HashMap;
+
+/// Bracketed shorthands, such as `(c)` or `[1/2]`, and the glyphs they
+/// stand for. Only the letters match regardless of case.
+const SHORTHANDS: [(&str, Symbol); 8] = [
+    ("tm", Symbol::Trademark),
+    ("r", Symbol::Registered),
+    ("c", Symbol::Copyright),
+    ("1/2", Symbol::Half),
+    ("1/4", Symbol::Quarter),
+    ("3/4", Symbol::ThreeQuarters),
+    ("o", Symbol::Degrees),
+    ("+/-", Symbol::PlusMinus),
+];
+
+/// Replaces quotes, dashes, ellipses and the bracketed shorthands in
+/// `text` with the glyphs in `symbols`, following php-textile's rules.
+/// `before` is whatever was written just before the text, which decides
+/// which way a leading quote faces. Glyphs missing from `symbols` are left
+/// as they were written.
+pub fn glyphs(text: &str, before: Option<char>, symbols: &HashMap<Symbol, String>) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::with_capacity(text.len());
+    let mut index = 0;
+    // Quotes opened so far and not yet closed, since a quote after a
+    // digit closes them rather than being a prime
+    let mut open_single = false;
+    let mut open_double = false;
+
+    while index < chars.len() {
+        let previous = if index == 0 {
+            before
+        } else {
+            Some(chars[index - 1])
+        };
+        let next = chars.get(index + 1).copied();
+        let push = |output: &mut String, symbol: Symbol, original: &str| {
+            output.push_str(symbols.get(&symbol).map_or(original, String::as_str))
+        };
+
+        match chars[index] {
+            '\'' => {
+                let symbol = match previous {
+                    Some(digit)
+                        if digit.is_ascii_digit() && !open_single && is_prime(&chars, index) =>
+                    {
+                        Symbol::Prime
+                    }
+                    _ => single_quote(&chars, index, previous, next),
+                };
+                match symbol {
+                    Symbol::QuoteSingleOpen => open_single = true,
+                    Symbol::QuoteSingleClose => open_single = false,
+                    _ => {}
+                }
+                push(&mut output, symbol, "'");
+            }
+            '"' => {
+                let symbol = match previous {
+                    Some(digit)
+                        if digit.is_ascii_digit() && !open_double && is_prime(&chars, index) =>
+                    {
+                        Symbol::PrimeDouble
+                    }
+                    _ => double_quote(previous, next),
+                };
+                open_double = symbol == Symbol::QuoteDoubleOpen;
+                push(&mut output, symbol, "\"");
+            }
+            'x' | 'X' if is_dimension(&chars, index) => {
+                push(&mut output, Symbol::Dimension, &chars[index].to_string())
+            }
+            '.' if next == Some('.') && chars.get(index + 2) == Some(&'.') => {
+                push(&mut output, Symbol::Ellipsis, "...");
+                index += 2;
+            }
+            '-' if next == Some('-') => {
+                push(&mut output, Symbol::Emdash, "--");
+                index += 1;
+            }
+            ' ' if next == Some('-') && chars.get(index + 2) == Some(&' ') => {
+                output.push(' ');
+                push(&mut output, Symbol::Endash, "-");
+                output.push(' ');
+                index += 2;
+            }
+            '(' | '[' => match shorthand(&chars, index, previous) {
+                Some((symbol, length)) => {
+                    let original: String = chars[index..index + length].iter().collect();
+                    push(&mut output, symbol, &original);
+                    index += length - 1;
+                }
+                None => output.push(chars[index]),
+            },
+            char => output.push(char),
+        }
+        index += 1;
+    }
+    output
+}
+
+/// Whether the quote at `index`, after a digit, is followed by what can
+/// come after a measurement, as in `6'2"`, `12" wide` or `4'x3'`
+fn is_prime(chars: &[char], index: usize) -> bool {
+    match chars.get(index + 1) {
+        Some('x' | 'X') => is_dimension(chars, index + 1),
+        Some(char) => !char.is_alphabetic(),
+        None => true,
+    }
+}
+
+/// Whether the `x` at `index` sits between two numbers, as in `10 x 20`,
+/// `4'x3'` or `2 x (3)`
+fn is_dimension(chars: &[char], index: usize) -> bool {
+    let mut before = chars[..index].iter().rev().peekable();
+    before.next_if_eq(&&' ');
+    before.next_if(|char| matches!(char, '\'' | '"'));
+    before.next_if(|char| matches!(char, ']' | ')'));
+    if before.next().is_none_or(|char| !char.is_ascii_digit()) {
+        return false;
+    }
+
+    let mut after = chars[index + 1..].iter().peekable();
+    after.next_if_eq(&&' ');
+    after.next_if(|char| matches!(char, '[' | '('));
+    after.next_if(|char| matches!(char, '+' | '-'));
+    after.next_if_eq(&&'.');
+    after.next().is_some_and(char::is_ascii_digit)
+}
+
+fn single_quote(
+    chars: &[char],
+    index: usize,
+    previous: Option<char>,
+    next: Option<char>,
+) -> Symbol {
+    let previous_is_word = previous.is_some_and(|char| is_word(char) || char == ')');
+    if previous_is_word && next.is_some_and(is_word) {
+        return Symbol::Apostrophe;
+    }
+    if previous.is_some_and(char::is_whitespace) && is_abbreviated_year(chars, index + 1) {
+        return Symbol::Apostrophe;
+    }
+    opening_or_closing(
+        previous,
+        next,
+        Symbol::QuoteSingleOpen,
+        Symbol::QuoteSingleClose,
+    )
+}
+
+fn double_quote(previous: Option<char>, next: Option<char>) -> Symbol {
+    opening_or_closing(
+        previous,
+        next,
+        Symbol::QuoteDoubleOpen,
+        Symbol::QuoteDoubleClose,
+    )
+}
+
+fn opening_or_closing(
+    previous: Option<char>,
+    next: Option<char>,
+    open: Symbol,
+    close: Symbol,
+) -> Symbol {
+    let next_is_space = next.is_none_or(char::is_whitespace);
+    if previous.is_some_and(|char| matches!(char, '(' | '[' | '{')) && !next_is_space {
+        return open;
+    }
+    let closes = next.is_none_or(|char| char.is_whitespace() || char.is_ascii_punctuation());
+    if previous.is_some_and(|char| !char.is_whitespace()) && closes {
+        return close;
+    }
+    open
+}
+
+/// Whether the digits at `start` make something like the `88` in `'88` or
+/// the `90s` in `the '90s`, rather than a quoted number such as `'10'`
+fn is_abbreviated_year(chars: &[char], start: usize) -> bool {
+    let digits = chars[start..]
+        .iter()
+        .take_while(|char| char.is_ascii_digit())
+        .count();
+    if digits == 0 {
+        return false;
+    }
+    let mut end = start + digits;
+    if chars.get(end).is_some_and(|char| is_word(*char)) {
+        end += 1;
+    }
+    if chars.get(end).is_some_and(|char| is_word(*char)) {
+        return false;
+    }
+    if chars.get(end) == Some(&'.') {
+        end += 1;
+    }
+    let rest = chars[end..].iter().skip_while(|char| is_word(**char));
+    rest.take(1).all(|char| *char != '\'')
+}
+
+/// Reads a bracketed shorthand such as `(tm)` at `index`, returning its
+/// glyph and length
+fn shorthand(chars: &[char], index: usize, previous: Option<char>) -> Option<(Symbol, usize)> {
+    for (shorthand, symbol) in SHORTHANDS {
+        let length = shorthand.chars().count() + 2;
+        let Some(candidate) = chars.get(index + 1..index + length) else {
+            continue;
+        };
+        let inner = &candidate[..candidate.len() - 1];
+        let letters = shorthand.chars().all(char::is_alphabetic);
+        // The degree sign's `o` has to be lowercase, unlike (TM) and co
+        let matches = inner.iter().zip(shorthand.chars()).all(|(char, expected)| {
+            if letters && expected != 'o' {
+                char.to_ascii_lowercase() == expected
+            } else {
+                *char == expected
+            }
+        });
+        if !matches || !matches!(candidate[candidate.len() - 1], ')' | ']') {
+            continue;
+        }
+        // Marks such as (c) follow a word, a space or nothing at all
+        let follows = previous.is_none_or(|char| char.is_whitespace() || is_word(char));
+        if is_mark(symbol) && !follows {
+            continue;
+        }
+        return Some((symbol, length));
+    }
+    None
+}
+
+fn is_mark(symbol: Symbol) -> bool {
+    matches!(
+        symbol,
+        Symbol::Trademark | Symbol::Registered | Symbol::Copyright
+    )
+}
+
+fn is_word(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use anyhow::Result;
+
+    fn plain(text: &str) -> String {
+        let symbols = HashMap::from([
+            (Symbol::QuoteSingleOpen, "‘".to_string()),
+            (Symbol::QuoteSingleClose, "’".to_string()),
+            (Symbol::QuoteDoubleOpen, "“".to_string()),
+            (Symbol::QuoteDoubleClose, "”".to_string()),
+            (Symbol::Apostrophe, "&#8217;".to_string()),
+            (Symbol::Ellipsis, "…".to_string()),
+            (Symbol::Emdash, "—".to_string()),
+            (Symbol::Endash, "–".to_string()),
+        ]);
+        glyphs(text, None, &symbols)
+    }
+
+    #[test]
+    fn quotes() -> Result<()> {
+        assert_eq!("“Hi,” she said. ‘OK’", plain("\"Hi,\" she said. 'OK'"));
+        assert_eq!(
+            "I&#8217;m in the &#8217;90s, not ‘10’",
+            plain("I'm in the '90s, not '10'")
+        );
+        assert_eq!("(“quoted”)", plain("(\"quoted\")"));
+        Ok(())
+    }
+
+    #[test]
+    fn primes_and_dimensions() -> Result<()> {
+        let symbols = Options::default().symbols;
+        assert_eq!(
+            "6&#8242;2&#8243; tall, 10 &#215; 20 and 4&#8242;&#215;3&#8242;",
+            glyphs("6'2\" tall, 10 x 20 and 4'x3'", None, &symbols)
+        );
+        assert_eq!(
+            "&#8220;Room 101&#8221; in the 1990&#8217;s, 0xdeadbeef, a x b",
+            glyphs(
+                "\"Room 101\" in the 1990's, 0xdeadbeef, a x b",
+                None,
+                &symbols
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dashes_and_ellipses() -> Result<()> {
+        assert_eq!("a—b – c…", plain("a--b - c..."));
+        assert_eq!("well…", plain("well..."));
+        Ok(())
+    }
+
+    #[test]
+    fn shorthands() -> Result<()> {
+        let symbols = Options::default().symbols;
+        assert_eq!(
+            "Textile&#8482; &#169; [&#189;] 20&#176;C &#177;1",
+            glyphs("Textile(TM) (c) [[1/2]] 20(o)C (+/-)1", None, &symbols)
+        );
+        assert_eq!("(O)", glyphs("(O)", None, &symbols));
+        Ok(())
+    }
+
+    #[test]
+    fn leading_quote_follows_what_came_before() -> Result<()> {
+        let symbols = Options::default().symbols;
+        assert_eq!("&#8221; end", glyphs("\" end", Some('>'), &symbols));
+        assert_eq!("&#8220;start", glyphs("\"start", None, &symbols));
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// How much of a file is read looking for its dimensions, which is enough
+/// to get past the metadata JPEGs may carry before their frame header
+const HEADER_LIMIT: u64 = 512 * 1024;
+
+/// Width and height of a local image, if `src` is a relative URL naming a
+/// PNG, JPEG, GIF or WebP file under `root`
+pub fn image_size(root: &Path, src: &str) -> Option<(u32, u32)> {
+    let path = local_path(src)?;
+    let root = root.canonicalize().ok()?;
+    // Resolving symlinks and `..` first keeps `../../etc/passwd` and
+    // friends from reading outside of the document root
+    let file = root.join(path).canonicalize().ok()?;
+    if !file.starts_with(&root) {
+        return None;
+    }
+
+    let mut header = vec![];
+    File::open(file)
+        .ok()?
+        .take(HEADER_LIMIT)
+        .read_to_end(&mut header)
+        .ok()?;
+    dimensions(&header)
+}
+
+/// The path part of a relative or root-relative URL
+fn local_path(src: &str) -> Option<&str> {
+    let end = src.find(['?', '#']).unwrap_or(src.len());
+    let path = &src[..end];
+    let scheme = path
+        .find(':')
+        .is_some_and(|colon| !path[..colon].contains('/'));
+    if scheme || path.starts_with("//") {
+        return None;
+    }
+    let path = path.trim_start_matches('/');
+    (!path.is_empty()).then_some(path)
+}
+
+fn dimensions(header: &[u8]) -> Option<(u32, u32)> {
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((big_endian(header, 16, 4)?, big_endian(header, 20, 4)?));
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return Some((little_endian(header, 6, 2)?, little_endian(header, 8, 2)?));
+    }
+    if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+        return webp(header);
+    }
+    if header.starts_with(b"\xff\xd8") {
+        return jpeg(header);
+    }
+    None
+}
+
+fn webp(header: &[u8]) -> Option<(u32, u32)> {
+    match header.get(12..16)? {
+        b"VP8 " => Some((
+            little_endian(header, 26, 2)? & 0x3fff,
+            little_endian(header, 28, 2)? & 0x3fff,
+        )),
+        b"VP8L" => {
+            let bits = little_endian(header, 21, 4)?;
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        b"VP8X" => Some((
+            little_endian(header, 24, 3)? + 1,
+            little_endian(header, 27, 3)? + 1,
+        )),
+        _ => None,
+    }
+}
+
+/// Walks the JPEG segments until the start of frame, which holds the
+/// dimensions
+fn jpeg(header: &[u8]) -> Option<(u32, u32)> {
+    let mut position = 2;
+    loop {
+        while header.get(position) == Some(&0xff) {
+            position += 1;
+        }
+        let marker = *header.get(position)?;
+        position += 1;
+        match marker {
+            0xd0..=0xd9 | 0x01 => continue,
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                return Some((
+                    big_endian(header, position + 5, 2)?,
+                    big_endian(header, position + 3, 2)?,
+                ));
+            }
+            _ => position += big_endian(header, position, 2)? as usize,
+        }
+    }
+}
+
+fn big_endian(bytes: &[u8], start: usize, length: usize) -> Option<u32> {
+    let bytes = bytes.get(start..start + length)?;
+    Some(
+        bytes
+            .iter()
+            .fold(0, |value, byte| value << 8 | u32::from(*byte)),
+    )
+}
+
+fn little_endian(bytes: &[u8], start: usize, length: usize) -> Option<u32> {
+    let bytes = bytes.get(start..start + length)?;
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| value << 8 | u32::from(*byte)),
+    )
+}
+
+/// A directory of its own for a test's files, removed when dropped
+#[cfg(test)]
+pub(crate) struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub(crate) fn new(name: &str) -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("unikko-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn png_and_gif() -> Result<()> {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 1, 44, 0, 0, 0, 200]);
+        assert_eq!(Some((300, 200)), dimensions(&png));
+        assert_eq!(Some((2, 258)), dimensions(b"GIF89a\x02\x00\x02\x01"));
+        Ok(())
+    }
+
+    #[test]
+    fn jpeg_after_metadata() -> Result<()> {
+        let jpeg = [
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x4a, 0x46, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x00,
+            0x30, 0x00, 0x40,
+        ];
+        assert_eq!(Some((64, 48)), dimensions(&jpeg));
+        Ok(())
+    }
+
+    #[test]
+    fn webp_variants() -> Result<()> {
+        let mut lossy = b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0\x9d\x01\x2a".to_vec();
+        lossy.extend_from_slice(&[0x80, 0x02, 0xe0, 0x01]);
+        assert_eq!(Some((640, 480)), dimensions(&lossy));
+        let mut extended = b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0\0\0\0\0".to_vec();
+        extended.extend_from_slice(&[0x7f, 0x02, 0x00, 0xdf, 0x01, 0x00]);
+        assert_eq!(Some((640, 480)), dimensions(&extended));
+        Ok(())
+    }
+
+    #[test]
+    fn only_local_paths() -> Result<()> {
+        assert_eq!(Some("img/a.png"), local_path("/img/a.png?v=2"));
+        assert_eq!(Some("a.png"), local_path("a.png"));
+        assert!(local_path("http://example.com/a.png").is_none());
+        assert!(local_path("//example.com/a.png").is_none());
+        assert!(local_path("data:image/png;base64,AAAA").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn paths_outside_the_root() -> Result<()> {
+        let dir = TestDir::new("paths_outside_the_root")?;
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(&root)?;
+        std::fs::write(root.join("a.gif"), b"GIF89a\x01\x00\x01\x00")?;
+        std::fs::write(dir.path().join("outside.gif"), b"GIF89a\x01\x00\x01\x00")?;
+        assert_eq!(Some((1, 1)), image_size(&root, "/a.gif"));
+        assert!(image_size(&root, "../outside.gif").is_none());
+        assert!(image_size(&root, "missing.gif").is_none());
+        Ok(())
+    }
+}
@@ -2,20 +2,20 @@ use thiserror::Error;
 
 mod convenience;
 mod error;
+mod glyphs;
+mod image_size;
 mod options;
 mod pulp;
 mod renderer;
 
 pub use error::Error;
-pub use options::Options;
+pub use options::{Doctype, LinkRel, LinkRelCallback, Options};
+pub use pulp::LinkAliases;
 
-pub fn textile_to_html_with_options<'a>(
-    textile: &'a str,
-    options: Options,
-) -> Result<String, Error> {
+pub fn textile_to_html_with_options(textile: &str, options: Options) -> Result<String, Error> {
     convenience::textile_to_html_with_options(textile, options)
 }
 
-pub fn textile_to_html<'a>(textile: &'a str) -> Result<String, Error> {
+pub fn textile_to_html(textile: &str) -> Result<String, Error> {
     convenience::textile_to_html_with_options(textile, Options::default())
 }
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-#[derive(Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Symbol {
     QuoteSingleOpen,
     QuoteSingleClose,
@@ -18,59 +18,152 @@ pub enum Symbol {
     ThreeQuarters,
     Degrees,
     PlusMinus,
+    Prime,
+    PrimeDouble,
+    Dimension,
+    /// Markup of a `[1]` footnote reference, filled in from `{atts}` and
+    /// `{marker}`
+    FootnoteReferencePattern,
+    /// Markup of the number starting a `fn1.` footnote, filled in from
+    /// `{atts}` and `{marker}`
+    FootnotePattern,
+    /// Markup of a `[#label]` note reference, filled in from `{atts}` and
+    /// `{marker}`
+    NoteReferencePattern,
+    /// Markup of a run of capitals, filled in from `{content}`. Without
+    /// one, capitals aren't wrapped at all.
+    Caps,
+    /// Markup of an acronym, filled in from `{title}` and `{content}`.
+    /// Without one, acronyms use the element the document type calls for.
+    Acronym,
 }
 
-enum Doctype {
+/// Decides a link's `rel` from its URL
+pub type LinkRelCallback = Box<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// What goes in the `rel` attribute of links
+pub enum LinkRel {
+    /// The same value, such as `nofollow noopener`, for every link
+    Value(String),
+    /// Decides each link's value from its URL, leaving the attribute out
+    /// when it returns `None`
+    Callback(LinkRelCallback),
+}
+
+impl LinkRel {
+    pub fn for_href(&self, href: &str) -> Option<String> {
+        match self {
+            LinkRel::Value(value) => Some(value.clone()),
+            LinkRel::Callback(callback) => callback(href),
+        }
+    }
+}
+
+/// The only schemes links and images may use in restricted mode
+pub(crate) const SAFE_URL_SCHEMES: [&str; 4] = ["http", "https", "ftp", "mailto"];
+
+/// The kind of HTML written, which decides the syntax of empty elements,
+/// `<acronym>` or `<abbr>`, how alignment is given unless
+/// [`Options::align_classes`] says otherwise, and whether languages are
+/// also given as `xml:lang`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Doctype {
     Html5,
     Xhtml,
 }
 
 pub struct Options {
     // setDocumentType
-    document_type: Doctype,
+    pub document_type: Doctype,
     // setDocumentRootDirectory
-    document_root_directory: Option<std::path::PathBuf>,
-    // // setLite
-    // lite_mode: bool,
-    // // setImages
-    // handle_images: bool,
-    // // setLinkRelationShip
-    // link_rel: Option<String>
-    // // setRestricted
-    // restricted_mode: bool
-    // // setRawBlocks
-    // setRawBlocks: bool, // defaults to false
-    // // setAlignClasses
-    // setAlignClasses: bool, // false
-    // // setBlockTags
-    // setBlockTags: bool, // true
-    // // setLineWrap
-    // setLineWrap: bool, // true
+    pub document_root_directory: Option<std::path::PathBuf>,
+    // setLite
+    /// Whether only paragraphs and block quotes are recognized, leaving
+    /// other block signatures, lists and tables as paragraph text
+    pub lite_mode: bool,
+    // setImages
+    /// Whether `!image!`s are rendered. Unless set, they are everywhere
+    /// but in restricted mode.
+    pub handle_images: Option<bool>,
+    // setLinkRelationShip
+    /// The `rel` of every link
+    pub link_rel: Option<LinkRel>,
+    // setRestricted
+    /// Whether the input is untrusted, in which case raw HTML is escaped,
+    /// only classes, ids and languages are kept as attributes, links get
+    /// `rel="nofollow"` and only `http`, `https`, `ftp` and `mailto` URLs
+    /// may be linked to, besides those without a scheme
+    pub restricted_mode: bool,
+    // setRawBlocks
+    /// Whether blocks starting with a block-level HTML tag are kept as
+    /// they are, rather than having their content marked up
+    pub raw_blocks: bool,
+    // setAlignClasses
+    /// Whether alignment is given as `align-left` style classes instead
+    /// of styles or `align` attributes. Unless set, it is for HTML5.
+    pub align_classes: Option<bool>,
+    // setBlockTags
+    /// Whether blocks without a signature are wrapped in `<p>`s
+    pub block_tags: bool,
+    // setLineWrap
+    /// Whether single newlines inside a block become line breaks, rather
+    /// than staying newlines
+    pub line_wrap: bool,
     // // setSymbol
     // setSymbol: (String, Option<String>), //
     pub symbols: HashMap<Symbol, String>,
-    // // setImagePrefix
-    // setImagePrefix: Option<String>, // none
-    // // setLinkPrefix
-    // setLinkPrefix: Option<String>,
-    // // setRelativeImagePrefix
-    // setRelativeImagePrefix: Option<String>, // deprecated
-    // // setDimensionlessImages
-    // setDimensionlessImages: bool, // false
+    // setImagePrefix
+    /// Put as it is in front of relative image URLs, those starting with
+    /// a word character
+    pub image_prefix: Option<String>,
+    // setLinkPrefix
+    /// Put as it is in front of relative link URLs, those starting with
+    /// a word character
+    pub link_prefix: Option<String>,
+    // setDimensionlessImages
+    /// Whether images are left without the `width` and `height` read from
+    /// their files
+    pub dimensionless_images: bool,
 }
 
-impl Options {
-    pub fn default() -> Self {
+impl Default for Options {
+    fn default() -> Self {
         Self {
             document_type: Doctype::Xhtml,
             document_root_directory: std::env::current_dir().ok(),
             symbols: Self::canonical_symbols(),
-            // lite_mode: false,
-            // handle_images: true,
-            // link_rel: None,
-            // restricted_mode: false,
+            image_prefix: None,
+            link_prefix: None,
+            dimensionless_images: false,
+            handle_images: None,
+            restricted_mode: false,
+            lite_mode: false,
+            raw_blocks: false,
+            align_classes: None,
+            block_tags: true,
+            line_wrap: true,
+            link_rel: None,
         }
     }
+}
+
+impl Options {
+    /// Sets both the image and link prefixes, like php-textile's
+    /// `setRelativeImagePrefix`
+    #[deprecated(note = "set `image_prefix` and `link_prefix` instead")]
+    pub fn set_relative_image_prefix(&mut self, prefix: &str) {
+        self.image_prefix = Some(prefix.to_string());
+        self.link_prefix = Some(prefix.to_string());
+    }
+
+    pub(crate) fn images_enabled(&self) -> bool {
+        self.handle_images.unwrap_or(!self.restricted_mode)
+    }
+
+    pub(crate) fn align_classes_enabled(&self) -> bool {
+        self.align_classes
+            .unwrap_or(matches!(self.document_type, Doctype::Html5))
+    }
 
     fn canonical_symbols() -> HashMap<Symbol, String> {
         let pairs = [
@@ -79,12 +172,12 @@ impl Options {
             (Symbol::QuoteDoubleOpen, "&#8220;"),
             (Symbol::QuoteDoubleClose, "&#8221;"),
             (Symbol::Apostrophe, "&#8217;"),
-            // ("prime", "&#8242;"),
-            // ("prime_double", "&#8243;"),
+            (Symbol::Prime, "&#8242;"),
+            (Symbol::PrimeDouble, "&#8243;"),
             (Symbol::Ellipsis, "&#8230;"),
             (Symbol::Emdash, "&#8212;"),
             (Symbol::Endash, "&#8211;"),
-            // ("dimension", "&#215;"),
+            (Symbol::Dimension, "&#215;"),
             (Symbol::Trademark, "&#8482;"),
             (Symbol::Registered, "&#174;"),
             (Symbol::Copyright, "&#169;"),
@@ -93,12 +186,14 @@ impl Options {
             (Symbol::ThreeQuarters, "&#190;"),
             (Symbol::Degrees, "&#176;"),
             (Symbol::PlusMinus, "&#177;"),
-            // ("fn_ref_pattern", "<sup{atts}>{marker}</sup>"),
-            // ("fn_foot_pattern", "<sup{atts}>{marker}</sup>"),
-            // ("nl_ref_pattern", "<sup{atts}>{marker}</sup>"),
-            // ("caps", "<span class=\"caps\">{content}</span>"),
-            // ("acronym", None),
+            (
+                Symbol::FootnoteReferencePattern,
+                "<sup{atts}>{marker}</sup>",
+            ),
+            (Symbol::FootnotePattern, "<sup{atts}>{marker}</sup>"),
+            (Symbol::NoteReferencePattern, "<sup{atts}>{marker}</sup>"),
+            (Symbol::Caps, "<span class=\"caps\">{content}</span>"),
         ];
-        return HashMap::from(pairs.map(|(key, value)| (key, value.to_string())));
+        HashMap::from(pairs.map(|(key, value)| (key, value.to_string())))
     }
 }
@@ -0,0 +1,94 @@
+/// An `ABC(Always Be Closing)` acronym. Ranges are byte offsets into the
+/// scanned text.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Acronym {
+    pub start: usize,
+    pub end: usize,
+    pub content: (usize, usize),
+    pub title: (usize, usize),
+}
+
+/// Tries to read an acronym, which is a capital followed by at least two
+/// more capitals or digits and then its title in parentheses
+pub fn acronym_at(text: &str, position: usize) -> Option<Acronym> {
+    if !starts_word(text, position)
+        || !text[position..].starts_with(|char: char| char.is_ascii_uppercase())
+    {
+        return None;
+    }
+    let length = text[position..]
+        .find(|char: char| !(char.is_ascii_uppercase() || char.is_ascii_digit()))
+        .unwrap_or(text.len() - position);
+    let content = (position, position + length);
+    if length < 3 || !text[content.1..].starts_with('(') {
+        return None;
+    }
+    let title_start = content.1 + 1;
+    let title_end = title_start + text[title_start..].find(')')?;
+
+    Some(Acronym {
+        start: position,
+        end: title_end + 1,
+        content,
+        title: (title_start, title_end),
+    })
+}
+
+/// Tries to read a run of at least three capitals at `position`, returning
+/// where the capitals end. Lowercase letters may follow, as in `CDs`, but
+/// aren't part of the run.
+pub fn caps_at(text: &str, position: usize) -> Option<usize> {
+    let previous = text[..position].chars().next_back();
+    if !previous.is_none_or(|char| char.is_whitespace() || matches!(char, '>' | '(' | ';' | '-')) {
+        return None;
+    }
+    let length = text[position..]
+        .find(|char: char| !char.is_uppercase())
+        .unwrap_or(text.len() - position);
+    let end = position + length;
+    if text[position..end].chars().count() < 3 {
+        return None;
+    }
+    let rest = text[end..].trim_start_matches(|char: char| char.is_lowercase());
+    rest.chars()
+        .next()
+        .is_none_or(|char| char.is_whitespace() || char.is_ascii_punctuation())
+        .then_some(end)
+}
+
+fn starts_word(text: &str, position: usize) -> bool {
+    text[..position]
+        .chars()
+        .next_back()
+        .is_none_or(|char| !(char.is_alphanumeric() || char == '_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn acronyms() -> Result<()> {
+        let text = "An ABC(Always Be Closing) rule";
+        let acronym = acronym_at(text, 3).unwrap();
+        assert_eq!("ABC", &text[acronym.content.0..acronym.content.1]);
+        assert_eq!("Always Be Closing", &text[acronym.title.0..acronym.title.1]);
+        assert_eq!(25, acronym.end);
+        assert!(acronym_at("W3C(World Wide Web Consortium)", 0).is_some());
+        assert!(acronym_at("AB(two)", 0).is_none());
+        assert!(acronym_at("xABC(no)", 1).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn caps() -> Result<()> {
+        assert_eq!(Some(4), caps_at("NASA's", 0));
+        assert_eq!(Some(5), caps_at("a CDE.", 2));
+        assert_eq!(Some(4), caps_at("(DVDs)", 1));
+        assert_eq!(None, caps_at("AB", 0));
+        assert_eq!(None, caps_at("aABC", 1));
+        assert_eq!(None, caps_at("ABCdef2", 0));
+        Ok(())
+    }
+}
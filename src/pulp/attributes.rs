@@ -0,0 +1,371 @@
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl VerticalAlign {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VerticalAlign::Top => "top",
+            VerticalAlign::Middle => "middle",
+            VerticalAlign::Bottom => "bottom",
+        }
+    }
+}
+
+impl Align {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Align::Left => "left",
+            Align::Right => "right",
+            Align::Center => "center",
+            Align::Justify => "justify",
+        }
+    }
+}
+
+/// The modifiers written between a signature and its dot, such as the
+/// `(class#id){color:red}[en]<` in `p(class#id){color:red}[en]<. text`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Attributes {
+    pub class: Option<String>,
+    pub id: Option<String>,
+    pub style: Option<String>,
+    pub lang: Option<String>,
+    pub align: Option<Align>,
+    pub padding_left: usize,
+    pub padding_right: usize,
+    pub vertical_align: Option<VerticalAlign>,
+    pub colspan: Option<usize>,
+    pub rowspan: Option<usize>,
+}
+
+impl Attributes {
+    /// Copy of the attributes for an element nested inside the one
+    /// carrying the id, since ids must stay unique
+    pub fn without_id(&self) -> Self {
+        Self {
+            id: None,
+            ..self.clone()
+        }
+    }
+
+    /// Copy of the attributes with only what restricted mode allows, which
+    /// is the class, id and language
+    pub fn restricted(&self) -> Self {
+        Self {
+            class: self.class.clone(),
+            id: self.id.clone(),
+            lang: self.lang.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds `align-left` and `align-top` style classes for the horizontal
+    /// and vertical alignment, in place of the `text-align` and
+    /// `vertical-align` declarations
+    pub fn align_as_class(&mut self) {
+        if let Some(align) = self.align.take() {
+            self.add_class(&format!("align-{}", align.as_str()));
+        }
+        if let Some(vertical_align) = self.vertical_align.take() {
+            self.add_class(&format!("align-{}", vertical_align.as_str()));
+        }
+    }
+
+    pub fn add_class(&mut self, class: &str) {
+        self.class = Some(match self.class.take() {
+            Some(existing) => format!("{} {}", existing, class),
+            None => class.to_string(),
+        });
+    }
+
+    /// Declarations for the `style` attribute, sorted like php-textile
+    pub fn style_declarations(&self) -> Vec<String> {
+        let mut declarations: Vec<String> = vec![];
+        if let Some(style) = &self.style {
+            for declaration in style.split(';') {
+                let declaration = declaration.trim().trim_matches(':');
+                if !declaration.is_empty() {
+                    declarations.push(declaration.to_string());
+                }
+            }
+        }
+        if self.padding_left > 0 {
+            declarations.push(format!("padding-left:{}em", self.padding_left));
+        }
+        if self.padding_right > 0 {
+            declarations.push(format!("padding-right:{}em", self.padding_right));
+        }
+        if let Some(align) = self.align {
+            declarations.push(format!("text-align:{}", align.as_str()));
+        }
+        if let Some(vertical_align) = self.vertical_align {
+            declarations.push(format!("vertical-align:{}", vertical_align.as_str()));
+        }
+        declarations.sort();
+        declarations
+    }
+}
+
+/// Parses as many attribute modifiers as possible from the start of
+/// `input`. Returns the attributes and the number of bytes consumed.
+pub fn parse_attributes(input: &str) -> (Attributes, usize) {
+    parse(input, Mode::Block)
+}
+
+/// Like `parse_attributes`, but only accepts the class, style and lang
+/// modifiers that phrases allow
+pub fn parse_phrase_attributes(input: &str) -> (Attributes, usize) {
+    parse(input, Mode::Phrase)
+}
+
+/// Like `parse_attributes`, but also accepts the vertical alignment and
+/// `\2` column or `/3` row spans of table cells and rows
+pub fn parse_cell_attributes(input: &str) -> (Attributes, usize) {
+    parse(input, Mode::Cell)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Phrase,
+    Block,
+    Cell,
+}
+
+fn parse(input: &str, mode: Mode) -> (Attributes, usize) {
+    let bytes = input.as_bytes();
+    let mut attributes = Attributes::default();
+    let mut position = 0;
+    let layout = mode != Mode::Phrase;
+    let cell = mode == Mode::Cell;
+
+    loop {
+        match bytes.get(position) {
+            Some(b'(') => match class_block(&input[position..], &mut attributes) {
+                Some(length) => position += length,
+                None if layout => {
+                    attributes.padding_left += 1;
+                    position += 1;
+                }
+                None => break,
+            },
+            Some(b'{') => match input[position..].find('}') {
+                Some(close) if close > 1 && attributes.style.is_none() => {
+                    attributes.style = Some(input[position + 1..position + close].to_string());
+                    position += close + 1;
+                }
+                _ => break,
+            },
+            Some(b'[') => match input[position..].find(']') {
+                Some(close) if close > 1 && attributes.lang.is_none() => {
+                    let lang = &input[position + 1..position + close];
+                    if is_lang(lang) {
+                        attributes.lang = Some(lang.to_string());
+                    } else if !layout {
+                        break;
+                    }
+                    position += close + 1;
+                }
+                _ => break,
+            },
+            Some(b')') if layout => {
+                attributes.padding_right += 1;
+                position += 1;
+            }
+            Some(b'<') if layout && bytes.get(position + 1) == Some(&b'>') => {
+                attributes.align = Some(Align::Justify);
+                position += 2;
+            }
+            Some(b'<') if layout => {
+                attributes.align = Some(Align::Left);
+                position += 1;
+            }
+            Some(b'>') if layout => {
+                attributes.align = Some(Align::Right);
+                position += 1;
+            }
+            Some(b'=') if layout => {
+                attributes.align = Some(Align::Center);
+                position += 1;
+            }
+            Some(b'^') if cell => {
+                attributes.vertical_align = Some(VerticalAlign::Top);
+                position += 1;
+            }
+            Some(b'-') if cell => {
+                attributes.vertical_align = Some(VerticalAlign::Middle);
+                position += 1;
+            }
+            Some(b'~') if cell => {
+                attributes.vertical_align = Some(VerticalAlign::Bottom);
+                position += 1;
+            }
+            Some(marker @ (b'\\' | b'/')) if cell => {
+                let digits = bytes[position + 1..]
+                    .iter()
+                    .take_while(|byte| byte.is_ascii_digit())
+                    .count();
+                if digits == 0 {
+                    break;
+                }
+                let span = input[position + 1..position + 1 + digits].parse().ok();
+                if *marker == b'\\' {
+                    attributes.colspan = span;
+                } else {
+                    attributes.rowspan = span;
+                }
+                position += 1 + digits;
+            }
+            _ => break,
+        }
+    }
+
+    (attributes, position)
+}
+
+/// Parses a `(class#id)` block, returning its length in bytes
+fn class_block(input: &str, attributes: &mut Attributes) -> Option<usize> {
+    if attributes.class.is_some() || attributes.id.is_some() {
+        return None;
+    }
+
+    let close = input[1..].find([')', '('])? + 1;
+    if close == 1 || input.as_bytes()[close] != b')' {
+        return None;
+    }
+
+    let content = &input[1..close];
+    let valid = |char: char| char.is_ascii_alphanumeric() || " -_/[].:#".contains(char);
+    if !content.chars().all(valid) {
+        return None;
+    }
+
+    let (class, id) = match content.find('#') {
+        Some(hash) => (&content[..hash], Some(&content[hash + 1..])),
+        None => (content, None),
+    };
+    let class = class.trim();
+    if !class.is_empty() && !class.contains('#') {
+        attributes.class = Some(class.to_string());
+    }
+    if let Some(id) = id {
+        if !id.is_empty() && !id.contains(['#', ' ', '/', '[', ']']) {
+            attributes.id = Some(id.to_string());
+        }
+    }
+
+    Some(close + 1)
+}
+
+/// Language codes look like `en`, `en-GB` or `en_GB`
+fn is_lang(lang: &str) -> bool {
+    let bytes = lang.as_bytes();
+    match bytes.len() {
+        2 => bytes.iter().all(u8::is_ascii_alphabetic),
+        5 => {
+            bytes[..2].iter().all(u8::is_ascii_alphabetic)
+                && (bytes[2] == b'-' || bytes[2] == b'_')
+                && bytes[3..].iter().all(u8::is_ascii_alphabetic)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn class_and_id() -> Result<()> {
+        let (attributes, length) = parse_attributes("(big#title). Hello");
+        assert_eq!(Some("big".to_string()), attributes.class);
+        assert_eq!(Some("title".to_string()), attributes.id);
+        assert_eq!(11, length);
+        Ok(())
+    }
+
+    #[test]
+    fn mixed_modifiers() -> Result<()> {
+        let (attributes, length) = parse_attributes("{color:red}[fr]((>. Bonjour");
+        assert_eq!(Some("color:red".to_string()), attributes.style);
+        assert_eq!(Some("fr".to_string()), attributes.lang);
+        assert_eq!(2, attributes.padding_left);
+        assert_eq!(Some(Align::Right), attributes.align);
+        assert_eq!(18, length);
+        assert_eq!(
+            vec!["color:red", "padding-left:2em", "text-align:right"],
+            attributes.style_declarations()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn padding_is_not_a_class() -> Result<()> {
+        let (attributes, length) = parse_attributes("(<>. Justified");
+        assert_eq!(None, attributes.class);
+        assert_eq!(1, attributes.padding_left);
+        assert_eq!(Some(Align::Justify), attributes.align);
+        assert_eq!(3, length);
+        Ok(())
+    }
+
+    #[test]
+    fn nothing_to_parse() -> Result<()> {
+        let (attributes, length) = parse_attributes(". Plain");
+        assert_eq!(Attributes::default(), attributes);
+        assert_eq!(0, length);
+        Ok(())
+    }
+
+    #[test]
+    fn phrase_attributes_skip_layout() -> Result<()> {
+        let (attributes, length) = parse_phrase_attributes("(note)[de]<text");
+        assert_eq!(Some("note".to_string()), attributes.class);
+        assert_eq!(Some("de".to_string()), attributes.lang);
+        assert_eq!(None, attributes.align);
+        assert_eq!(10, length);
+        assert_eq!(0, parse_phrase_attributes("((text").1);
+        Ok(())
+    }
+
+    #[test]
+    fn cell_spans_and_vertical_align() -> Result<()> {
+        let (attributes, length) = parse_cell_attributes("\\2/3^<(x). cell");
+        assert_eq!(Some(2), attributes.colspan);
+        assert_eq!(Some(3), attributes.rowspan);
+        assert_eq!(Some(VerticalAlign::Top), attributes.vertical_align);
+        assert_eq!(Some(Align::Left), attributes.align);
+        assert_eq!(Some("x".to_string()), attributes.class);
+        assert_eq!(9, length);
+        assert_eq!(0, parse_attributes("^. not a cell").1);
+        Ok(())
+    }
+
+    #[test]
+    fn alignment_classes() -> Result<()> {
+        let (mut attributes, _) = parse_attributes("(note)<>. text");
+        attributes.align_as_class();
+        assert_eq!(Some("note align-justify".to_string()), attributes.class);
+        assert!(attributes.style_declarations().is_empty());
+        let (mut attributes, _) = parse_cell_attributes("~>. cell");
+        attributes.align_as_class();
+        assert_eq!(
+            Some("align-right align-bottom".to_string()),
+            attributes.class
+        );
+        assert!(attributes.style_declarations().is_empty());
+        Ok(())
+    }
+}
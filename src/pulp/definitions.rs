@@ -0,0 +1,153 @@
+use super::attributes::{parse_phrase_attributes, Attributes};
+
+/// A `; term` or `: definition` line
+#[derive(Debug, Eq, PartialEq)]
+pub struct DefinitionLine {
+    pub term: bool,
+    pub attributes: Attributes,
+    /// Byte offset at which the content starts
+    pub offset: usize,
+}
+
+pub fn definition_line(line: &str) -> Option<DefinitionLine> {
+    let term = match line.as_bytes().first() {
+        Some(b';') => true,
+        Some(b':') => false,
+        _ => return None,
+    };
+
+    let (attributes, length) = parse_phrase_attributes(&line[1..]);
+    let mut offset = 1 + length;
+    if line.as_bytes().get(offset) != Some(&b' ') {
+        return None;
+    }
+    while line.as_bytes().get(offset) == Some(&b' ') {
+        offset += 1;
+    }
+
+    Some(DefinitionLine {
+        term,
+        attributes,
+        offset,
+    })
+}
+
+/// A `- term := definition` line. Ranges are byte offsets into the line.
+#[derive(Debug, Eq, PartialEq)]
+pub struct TermLine {
+    pub attributes: Attributes,
+    pub term: (usize, usize),
+    /// The definition following `:=` on the same line, if there was one
+    pub definition: Option<(usize, usize)>,
+    /// Whether the line has `:=` but no definition, so the definition
+    /// follows on the next lines
+    pub multiline: bool,
+    /// Whether the definition was closed with `=:`
+    pub closed: bool,
+}
+
+pub fn term_line(line: &str) -> Option<TermLine> {
+    if !line.starts_with('-') {
+        return None;
+    }
+
+    let (attributes, length) = parse_phrase_attributes(&line[1..]);
+    let mut start = 1 + length;
+    if line.as_bytes().get(start) == Some(&b'.') {
+        start += 1;
+    }
+    if line.as_bytes().get(start) != Some(&b' ') {
+        return None;
+    }
+
+    let (term_end, rest) = match line[start..].find(":=") {
+        Some(found) => (start + found, Some(start + found + 2)),
+        None => (line.len(), None),
+    };
+    let term = trimmed(line, start, term_end);
+    if term.0 == term.1 {
+        return None;
+    }
+
+    let mut definition = None;
+    let mut closed = false;
+    if let Some(rest) = rest {
+        let (end, is_closed) = closing(line, rest);
+        closed = is_closed;
+        let range = trimmed(line, rest, end);
+        if range.0 < range.1 {
+            definition = Some(range);
+        }
+    }
+
+    Some(TermLine {
+        attributes,
+        term,
+        multiline: rest.is_some() && definition.is_none() && !closed,
+        definition,
+        closed,
+    })
+}
+
+/// Finds a trailing `=:`, returning where the text before it ends
+pub fn closing(line: &str, start: usize) -> (usize, bool) {
+    let trimmed = line[start..].trim_end();
+    match trimmed.strip_suffix("=:") {
+        Some(rest) => (start + rest.len(), true),
+        None => (line.len(), false),
+    }
+}
+
+fn trimmed(line: &str, start: usize, end: usize) -> (usize, usize) {
+    let text = &line[start..end];
+    let leading = text.len() - text.trim_start().len();
+    let trailing = text.len() - text.trim_end().len();
+    if leading == text.len() {
+        return (start, start);
+    }
+    (start + leading, end - trailing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn semicolon_and_colon() -> Result<()> {
+        let line = definition_line("; term").unwrap();
+        assert!(line.term);
+        assert_eq!(2, line.offset);
+        let line = definition_line(":(x) definition").unwrap();
+        assert!(!line.term);
+        assert_eq!(Some("x".to_string()), line.attributes.class);
+        assert!(definition_line(":nope").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn term_and_definition() -> Result<()> {
+        let line = term_line("- coffee := a drink =:").unwrap();
+        assert_eq!((2, 8), line.term);
+        assert_eq!(Some((12, 19)), line.definition);
+        assert!(line.closed);
+        assert!(!line.multiline);
+        Ok(())
+    }
+
+    #[test]
+    fn multiline_definition() -> Result<()> {
+        let line = term_line("- tea :=").unwrap();
+        assert_eq!((2, 5), line.term);
+        assert_eq!(None, line.definition);
+        assert!(line.multiline);
+        Ok(())
+    }
+
+    #[test]
+    fn not_a_term() -> Result<()> {
+        assert!(term_line("-strike-").is_none());
+        assert!(term_line("- ").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use super::links::LinkAliases;
+use crate::Options;
+
+/// FNV-1a, which unlike the standard library's hasher gives the same
+/// value for the same input in every build
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// What is collected from the whole document before parsing, for events
+/// that refer to something defined elsewhere in it
+#[derive(Clone, Debug)]
+pub struct Document<'a> {
+    pub link_aliases: LinkAliases<'a>,
+    /// Starts every generated id, so that the footnotes of several
+    /// documents shown on one page don't clash
+    pub id_prefix: String,
+}
+
+impl<'a> Document<'a> {
+    pub fn new(input: &'a str, options: &Options) -> Self {
+        Self {
+            link_aliases: LinkAliases::new_with_options(input, options),
+            id_prefix: format!("{:016x}", fnv1a(input.as_bytes())),
+        }
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn stable_id_prefix() -> Result<()> {
+        assert_eq!(
+            "cbf29ce484222325",
+            Document::new("", &Options::default()).id_prefix
+        );
+        assert_eq!(
+            "af63dc4c8601ec8c",
+            Document::new("a", &Options::default()).id_prefix
+        );
+        assert_eq!(
+            "85944171f73967e8",
+            Document::new("foobar", &Options::default()).id_prefix
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+/// A `[1]` footnote reference. Ranges are byte offsets into the scanned
+/// text.
+#[derive(Debug, Eq, PartialEq)]
+pub struct FootnoteReference {
+    pub start: usize,
+    pub end: usize,
+    pub number: (usize, usize),
+    /// Whether the reference links to its footnote, which `[1!]` turns off
+    pub link: bool,
+}
+
+/// Tries to read a footnote reference starting at `position`. References
+/// follow a word directly, as in `word[1]`.
+pub fn footnote_reference(text: &str, position: usize) -> Option<FootnoteReference> {
+    let bytes = text.as_bytes();
+    if bytes[position] != b'[' || !text[..position].ends_with(|char: char| !char.is_whitespace()) {
+        return None;
+    }
+
+    let number = (position + 1, position + 1 + digits(&text[position + 1..]));
+    if number.0 == number.1 {
+        return None;
+    }
+    let mut end = number.1;
+    let link = bytes.get(end) != Some(&b'!');
+    if !link {
+        end += 1;
+    }
+    if bytes.get(end) != Some(&b']') {
+        return None;
+    }
+
+    Some(FootnoteReference {
+        start: position,
+        end: end + 1,
+        number,
+        link,
+    })
+}
+
+/// Length of the run of ASCII digits that `text` starts with
+pub fn digits(text: &str) -> usize {
+    text.bytes().take_while(u8::is_ascii_digit).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn references() -> Result<()> {
+        let reference = footnote_reference("word[12] more", 4).unwrap();
+        assert_eq!((5, 7), reference.number);
+        assert!(reference.link);
+        assert_eq!(8, reference.end);
+
+        let reference = footnote_reference("word[3!]", 4).unwrap();
+        assert!(!reference.link);
+        assert_eq!(8, reference.end);
+        Ok(())
+    }
+
+    #[test]
+    fn not_references() -> Result<()> {
+        assert!(footnote_reference("[1] first", 0).is_none());
+        assert!(footnote_reference("a [1]", 2).is_none());
+        assert!(footnote_reference("a[b]", 1).is_none());
+        assert!(footnote_reference("a[1", 1).is_none());
+        Ok(())
+    }
+}
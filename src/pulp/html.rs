@@ -0,0 +1,181 @@
+/// Elements that make a block of their own, so a block starting with one
+/// isn't wrapped in a paragraph
+const BLOCK_TAGS: [&str; 26] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "div",
+    "dl",
+    "fieldset",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// An HTML start, end or empty-element tag such as `<span class="a">`,
+/// `</span>` or `<br />`
+#[derive(Debug, Eq, PartialEq)]
+pub struct Tag<'a> {
+    pub end: usize,
+    pub name: &'a str,
+    pub closing: bool,
+}
+
+/// How the content of a block starting with HTML is treated
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlockKind {
+    /// An `<!-- comment -->`, kept as it is
+    Comment,
+    /// A `<pre>`, whose content is escaped rather than marked up
+    Preformatted,
+    /// Any other block-level element, whose content is still Textile
+    Markup,
+}
+
+/// Tries to read a tag starting at `position`. Quoted attribute values
+/// may contain `>`, but a `<` anywhere means it isn't a tag after all.
+pub fn tag_at(text: &str, position: usize) -> Option<Tag<'_>> {
+    let rest = text[position..].strip_prefix('<')?;
+    let closing = rest.starts_with('/');
+    let name_start = position + 1 + usize::from(closing);
+    if !text[name_start..].starts_with(|char: char| char.is_ascii_alphabetic()) {
+        return None;
+    }
+    let name_end = text[name_start..]
+        .find(|char: char| !(char.is_ascii_alphanumeric() || char == '-'))
+        .map_or(text.len(), |found| name_start + found);
+
+    let mut quote = None;
+    for (offset, char) in text[name_end..].char_indices() {
+        match (quote, char) {
+            (Some(open), _) if char == open => quote = None,
+            (Some(_), _) => {}
+            (None, '>') => {
+                return Some(Tag {
+                    end: name_end + offset + 1,
+                    name: &text[name_start..name_end],
+                    closing,
+                })
+            }
+            (None, '<') => return None,
+            (None, '"' | '\'') if !closing => quote = Some(char),
+            (None, _) if offset == 0 && !(char.is_whitespace() || char == '/') => return None,
+            (None, char) if closing && !char.is_whitespace() => return None,
+            (None, _) => {}
+        }
+    }
+    None
+}
+
+/// Tries to read an `<!-- comment -->` starting at `position`, returning
+/// where it ends
+pub fn comment_at(text: &str, position: usize) -> Option<usize> {
+    if !text[position..].starts_with("<!--") {
+        return None;
+    }
+    let content = position + 4;
+    text[content..].find("-->").map(|found| content + found + 3)
+}
+
+/// Whether `line` starts a block of HTML, and what it holds
+pub fn block_kind(line: &str) -> Option<BlockKind> {
+    if line.starts_with("<!--") {
+        return Some(BlockKind::Comment);
+    }
+    let tag = tag_at(line, 0)?;
+    let name = tag.name.to_ascii_lowercase();
+    if !BLOCK_TAGS.contains(&name.as_str()) {
+        return None;
+    }
+    if name == "pre" && !tag.closing {
+        Some(BlockKind::Preformatted)
+    } else {
+        Some(BlockKind::Markup)
+    }
+}
+
+/// Finds the `</name>` closing a tag, searching from `position`, and
+/// returns where it starts
+pub fn closing_tag(text: &str, position: usize, name: &str) -> Option<usize> {
+    let mut search = position;
+    while let Some(found) = text[search..].find("</") {
+        let start = search + found;
+        if let Some(tag) = tag_at(text, start) {
+            if tag.name.eq_ignore_ascii_case(name) {
+                return Some(start);
+            }
+        }
+        search = start + 2;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn tags() -> Result<()> {
+        let text = "a <span class=\"x>y\">b</span> <br/> <hr />";
+        let tag = tag_at(text, 2).unwrap();
+        assert_eq!("span", tag.name);
+        assert!(!tag.closing);
+        assert_eq!(20, tag.end);
+        let tag = tag_at(text, 21).unwrap();
+        assert!(tag.closing);
+        assert_eq!(28, tag.end);
+        assert_eq!(Some(34), tag_at(text, 29).map(|tag| tag.end));
+        assert_eq!(Some(text.len()), tag_at(text, 35).map(|tag| tag.end));
+        assert!(tag_at("a < b > c", 2).is_none());
+        assert!(tag_at("<b <i>", 0).is_none());
+        assert!(tag_at("<bad-", 0).is_none());
+        assert!(tag_at("</b x>", 0).is_none());
+        assert!(tag_at("<3>", 0).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn comments() -> Result<()> {
+        assert_eq!(Some(13), comment_at("<!-- note -->!", 0));
+        assert_eq!(None, comment_at("<!-- unclosed", 0));
+        assert_eq!(None, comment_at("<!- no -->", 0));
+        Ok(())
+    }
+
+    #[test]
+    fn block_kinds() -> Result<()> {
+        assert_eq!(Some(BlockKind::Markup), block_kind("<div class=\"a\">"));
+        assert_eq!(Some(BlockKind::Markup), block_kind("</DIV>"));
+        assert_eq!(Some(BlockKind::Preformatted), block_kind("<pre>x"));
+        assert_eq!(Some(BlockKind::Comment), block_kind("<!-- x"));
+        assert_eq!(None, block_kind("<span>x</span>"));
+        assert_eq!(None, block_kind(" <div>"));
+        Ok(())
+    }
+
+    #[test]
+    fn closing_tags() -> Result<()> {
+        let text = "<code>a</b></CODE>";
+        assert_eq!(Some(11), closing_tag(text, 6, "code"));
+        assert_eq!(None, closing_tag(text, 12, "code"));
+        Ok(())
+    }
+}
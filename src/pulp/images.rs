@@ -0,0 +1,150 @@
+use super::attributes::{parse_phrase_attributes, Align, Attributes};
+use super::links::trim_url;
+
+/// A `!src(alt)!:url` image. Ranges are byte offsets into the scanned
+/// text.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Image {
+    pub start: usize,
+    pub end: usize,
+    pub align: Option<Align>,
+    pub attributes: Attributes,
+    pub src: (usize, usize),
+    pub alt: Option<(usize, usize)>,
+    /// Where the image links to, from a `:url` after the closing `!`
+    pub href: Option<(usize, usize)>,
+}
+
+/// Tries to read an image starting at `position`, either bare
+/// (`!src!`) or bracketed (`[!src!]`)
+pub fn image_at(text: &str, position: usize) -> Option<Image> {
+    let bytes = text.as_bytes();
+    let bracketed = bytes[position] == b'[';
+    let mut cursor = if bracketed { position + 1 } else { position };
+    if bytes.get(cursor) != Some(&b'!') {
+        return None;
+    }
+    cursor += 1;
+
+    let align = match bytes.get(cursor) {
+        Some(b'<') => Some(Align::Left),
+        Some(b'=') => Some(Align::Center),
+        Some(b'>') => Some(Align::Right),
+        _ => None,
+    };
+    if align.is_some() {
+        cursor += 1;
+    }
+
+    let (attributes, length) = parse_phrase_attributes(&text[cursor..]);
+    cursor += length;
+    if length > 0 && text[cursor..].starts_with(". ") {
+        cursor += 2;
+    }
+
+    let src_start = cursor;
+    let src_length = text[cursor..]
+        .find(|char: char| char.is_whitespace() || char == '(' || char == '!')
+        .unwrap_or(text.len() - cursor);
+    if src_length == 0 {
+        return None;
+    }
+    cursor += src_length;
+    let src = (src_start, cursor);
+
+    if bytes.get(cursor) == Some(&b' ') {
+        cursor += 1;
+    }
+    let mut alt = None;
+    if bytes.get(cursor) == Some(&b'(') {
+        let close = cursor + text[cursor..].find(')')?;
+        if close == cursor + 1 {
+            return None;
+        }
+        alt = Some((cursor + 1, close));
+        cursor = close + 1;
+    }
+    if bytes.get(cursor) != Some(&b'!') {
+        return None;
+    }
+    cursor += 1;
+
+    let mut href = None;
+    if bytes.get(cursor) == Some(&b':') {
+        let href_start = cursor + 1;
+        let href_end = if bracketed {
+            href_start
+                + text[href_start..].find(|char: char| char == ']' || char.is_whitespace())?
+        } else {
+            let whitespace = text[href_start..]
+                .find(char::is_whitespace)
+                .map_or(text.len(), |found| href_start + found);
+            trim_url(text, href_start, whitespace)
+        };
+        if href_end > href_start {
+            href = Some((href_start, href_end));
+            cursor = href_end;
+        }
+    }
+    if bracketed {
+        if bytes.get(cursor) != Some(&b']') {
+            return None;
+        }
+        cursor += 1;
+    }
+
+    Some(Image {
+        start: position,
+        end: cursor,
+        align,
+        attributes,
+        src,
+        alt,
+        href,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    fn slice(text: &str, range: (usize, usize)) -> &str {
+        &text[range.0..range.1]
+    }
+
+    #[test]
+    fn image_with_alt() -> Result<()> {
+        let text = "a !(photo)/cat.png (A cat)! b";
+        let image = image_at(text, 2).unwrap();
+        assert_eq!(Some("photo".to_string()), image.attributes.class);
+        assert_eq!("/cat.png", slice(text, image.src));
+        assert_eq!("A cat", slice(text, image.alt.unwrap()));
+        assert!(image.href.is_none());
+        assert_eq!(text.len() - 2, image.end);
+        Ok(())
+    }
+
+    #[test]
+    fn aligned_and_linked() -> Result<()> {
+        let text = "!>cat.png!:http://example.com/.";
+        let image = image_at(text, 0).unwrap();
+        assert_eq!(Some(Align::Right), image.align);
+        assert_eq!("http://example.com/", slice(text, image.href.unwrap()));
+        assert_eq!(text.len() - 1, image.end);
+
+        let text = "x[!cat.png!:/cats]y";
+        let image = image_at(text, 1).unwrap();
+        assert_eq!("/cats", slice(text, image.href.unwrap()));
+        assert_eq!(text.len() - 1, image.end);
+        Ok(())
+    }
+
+    #[test]
+    fn not_images() -> Result<()> {
+        assert!(image_at("Wow! Such text!", 3).is_none());
+        assert!(image_at("!!", 0).is_none());
+        assert!(image_at("!cat.png", 0).is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+use super::attributes::{parse_phrase_attributes, Attributes};
+use super::pass_2::SecondPass;
+use super::pass_3::at_boundary;
+use crate::options::SAFE_URL_SCHEMES;
+use crate::Options;
+use std::collections::HashMap;
+
+/// A `"text(title)":url` link. Ranges are byte offsets into the scanned
+/// text.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Link {
+    pub start: usize,
+    pub end: usize,
+    pub attributes: Attributes,
+    pub text: (usize, usize),
+    pub title: Option<(usize, usize)>,
+    pub href: (usize, usize),
+}
+
+/// Tries to read a link starting at `position`, either bare
+/// (`"text":url`) or bracketed (`["text":url]`)
+pub fn link_at(text: &str, position: usize) -> Option<Link> {
+    let bytes = text.as_bytes();
+    let bracketed = bytes[position] == b'[';
+    let quote = if bracketed { position + 1 } else { position };
+    if bytes.get(quote) != Some(&b'"') || (!bracketed && !at_boundary(text, position)) {
+        return None;
+    }
+
+    let text_start = quote + 1;
+    let text_end = text_start + text[text_start..].find('"')?;
+    if text_end == text_start || bytes.get(text_end + 1) != Some(&b':') {
+        return None;
+    }
+
+    let href_start = text_end + 2;
+    let (href_end, end) = if bracketed {
+        let close = href_start + text[href_start..].find(']')?;
+        if text[href_start..close].contains(char::is_whitespace) {
+            return None;
+        }
+        (close, close + 1)
+    } else {
+        let whitespace = text[href_start..]
+            .find(char::is_whitespace)
+            .map_or(text.len(), |found| href_start + found);
+        let href_end = trim_url(text, href_start, whitespace);
+        (href_end, href_end)
+    };
+    if href_end == href_start {
+        return None;
+    }
+
+    let (mut attributes, length) = parse_phrase_attributes(&text[text_start..text_end]);
+    let mut content_start = text_start + length;
+    if content_start == text_end {
+        attributes = Attributes::default();
+        content_start = text_start;
+    }
+    let (content_end, title) = title(text, content_start, text_end);
+
+    Some(Link {
+        start: position,
+        end,
+        attributes,
+        text: (content_start, content_end),
+        title,
+        href: (href_start, href_end),
+    })
+}
+
+/// Splits a trailing `(title)` off link text, returning where the text
+/// ends and the title range
+fn title(text: &str, start: usize, end: usize) -> (usize, Option<(usize, usize)>) {
+    let content = &text[start..end];
+    if !content.ends_with(')') {
+        return (end, None);
+    }
+    let Some(open) = content.rfind('(') else {
+        return (end, None);
+    };
+    let before = content[..open].trim_end();
+    if before.is_empty() || open + 1 == content.len() - 1 {
+        return (end, None);
+    }
+    (start + before.len(), Some((start + open + 1, end - 1)))
+}
+
+/// Drops punctuation that more likely ends the sentence than the URL,
+/// keeping a closing parenthesis when the URL opened one
+pub fn trim_url(text: &str, start: usize, mut end: usize) -> usize {
+    while let Some(last) = text[start..end].chars().next_back() {
+        if last.is_alphanumeric() || last == '/' || last == '_' {
+            break;
+        }
+        if last == ')' {
+            let url = &text[start..end];
+            if url.matches('(').count() >= url.matches(')').count() {
+                break;
+            }
+        }
+        end -= last.len_utf8();
+    }
+    end
+}
+
+/// The text shown for a `"$":url` link, which is the URL without its
+/// scheme
+pub fn self_link_text(href: &str) -> &str {
+    for scheme in ["mailto:", "tel:"] {
+        if let Some(rest) = href.strip_prefix(scheme) {
+            return rest;
+        }
+    }
+    match href.find("://") {
+        Some(found)
+            if href[..found]
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric()) =>
+        {
+            &href[found + 3..]
+        }
+        _ => href,
+    }
+}
+
+/// The scheme of a URL, such as the `https` of `https://example.com/`
+pub fn scheme(url: &str) -> Option<&str> {
+    let scheme = &url[..url.find(':')?];
+    let valid = scheme.starts_with(|char: char| char.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '+' | '-' | '.'));
+    valid.then_some(scheme)
+}
+
+/// Whether restricted mode allows linking to a URL, which it does when
+/// the URL has no scheme or one of the safe ones
+pub fn is_safe_url(url: &str) -> bool {
+    scheme(url).is_none_or(|scheme| {
+        SAFE_URL_SCHEMES
+            .iter()
+            .any(|safe| safe.eq_ignore_ascii_case(scheme))
+    })
+}
+
+/// The `[alias]url` definitions of a document, which `"text":alias` links
+/// resolve against wherever in the document they appear
+#[derive(Clone, Debug, Default)]
+pub struct LinkAliases<'a> {
+    /// Each URL along with the byte offset it was defined at
+    aliases: HashMap<&'a str, (usize, &'a str)>,
+}
+
+impl<'a> LinkAliases<'a> {
+    /// Collects every definition in the document. A later definition of
+    /// the same alias replaces the earlier one.
+    pub fn new(textile: &'a str) -> Self {
+        Self::new_with_options(textile, &Options::default())
+    }
+
+    /// Collects the definitions the second pass leaves out of the output,
+    /// so that lines inside literal and raw blocks don't count as ones
+    pub(crate) fn new_with_options(textile: &'a str, options: &Options) -> Self {
+        let mut second_pass = SecondPass::new_with_options(textile, options);
+        second_pass.by_ref().for_each(drop);
+        second_pass.into_link_aliases()
+    }
+
+    /// Defines an alias whose URL starts at byte offset `offset`
+    pub(crate) fn define(&mut self, alias: &'a str, offset: usize, url: &'a str) {
+        self.aliases.insert(alias, (offset, url));
+    }
+
+    /// The URL defined for an alias
+    pub fn get(&self, alias: &str) -> Option<&'a str> {
+        self.aliases.get(alias).map(|(_, url)| *url)
+    }
+
+    /// The URL defined for an alias and the byte offset of its definition
+    pub(crate) fn locate(&self, alias: &str) -> Option<(usize, &'a str)> {
+        self.aliases.get(alias).copied()
+    }
+
+    /// The URL to link to, which is either the one defined for the alias
+    /// or the href itself
+    pub fn resolve(&self, href: &'a str) -> &'a str {
+        self.get(href).unwrap_or(href)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.aliases.iter().map(|(alias, (_, url))| (*alias, *url))
+    }
+}
+
+/// Reads a line made of only an `[alias]url` definition, returning the
+/// alias and its URL
+pub fn alias_definition(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_end();
+    let rest = line.strip_prefix('[')?;
+    let close = rest.find(']')?;
+    let (alias, url) = (&rest[..close], &rest[close + 1..]);
+    if alias.is_empty() || url.contains(char::is_whitespace) {
+        return None;
+    }
+    let absolute = url
+        .find("://")
+        .filter(|found| *found > 0)
+        .is_some_and(|found| {
+            url[..found]
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric())
+        });
+    if !(absolute || url.starts_with('/') || url.starts_with("mailto:")) {
+        return None;
+    }
+    Some((alias, url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    fn slice(text: &str, range: (usize, usize)) -> &str {
+        &text[range.0..range.1]
+    }
+
+    #[test]
+    fn link_with_title_and_class() -> Result<()> {
+        let text = "see \"(ext)Example (site)\":http://example.com/.";
+        let link = link_at(text, 4).unwrap();
+        assert_eq!(Some("ext".to_string()), link.attributes.class);
+        assert_eq!("Example", slice(text, link.text));
+        assert_eq!("site", slice(text, link.title.unwrap()));
+        assert_eq!("http://example.com/", slice(text, link.href));
+        assert_eq!(text.len() - 1, link.end);
+        Ok(())
+    }
+
+    #[test]
+    fn bracketed_link() -> Result<()> {
+        let text = "a[\"b\":http://example.com/c]d";
+        let link = link_at(text, 1).unwrap();
+        assert_eq!("http://example.com/c", slice(text, link.href));
+        assert_eq!(text.len() - 1, link.end);
+        Ok(())
+    }
+
+    #[test]
+    fn balanced_parentheses() -> Result<()> {
+        let text = "\"w\":http://e.org/A_(b)), then";
+        let link = link_at(text, 0).unwrap();
+        assert_eq!("http://e.org/A_(b)", slice(text, link.href));
+        Ok(())
+    }
+
+    #[test]
+    fn not_links() -> Result<()> {
+        assert!(link_at("\"quoted\" text", 0).is_none());
+        assert!(link_at("\"text\": later", 0).is_none());
+        assert!(link_at("a\"b\":c", 1).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn self_links() -> Result<()> {
+        assert_eq!("example.com/a", self_link_text("https://example.com/a"));
+        assert_eq!("me@example.com", self_link_text("mailto:me@example.com"));
+        assert_eq!("/relative", self_link_text("/relative"));
+        Ok(())
+    }
+
+    #[test]
+    fn schemes() -> Result<()> {
+        assert_eq!(Some("https"), scheme("https://example.com/"));
+        assert_eq!(Some("mailto"), scheme("mailto:me@example.com"));
+        assert_eq!(None, scheme("/a:b"));
+        assert_eq!(None, scheme("relative"));
+        assert!(is_safe_url("HTTP://example.com/"));
+        assert!(is_safe_url("page#top"));
+        assert!(!is_safe_url("javascript:alert(1)"));
+        assert!(!is_safe_url("data:text/html,x"));
+        Ok(())
+    }
+
+    #[test]
+    fn aliases() -> Result<()> {
+        let aliases = LinkAliases::new(
+            "\"a\":home\n\n[home]http://example.com/\n[up]/parent\n[home]https://example.org/",
+        );
+        assert_eq!(Some("https://example.org/"), aliases.get("home"));
+        assert_eq!("/parent", aliases.resolve("up"));
+        assert_eq!("elsewhere", aliases.resolve("elsewhere"));
+        assert_eq!(2, aliases.iter().count());
+        assert_eq!(Some((40, "/parent")), aliases.locate("up"));
+        let aliases = LinkAliases::new("\"l\":x\n\n<pre>\n[x]http://a.com/\n</pre>\n\nbc.. [y]/y");
+        assert_eq!(None, aliases.get("x"));
+        assert_eq!(None, aliases.get("y"));
+        assert!(alias_definition("[note] http://example.com/").is_none());
+        assert!(alias_definition("[1]").is_none());
+        Ok(())
+    }
+}
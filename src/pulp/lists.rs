@@ -0,0 +1,136 @@
+use super::attributes::{parse_phrase_attributes, Attributes};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ListKind {
+    Unordered,
+    Ordered,
+}
+
+impl ListKind {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            ListKind::Unordered => "ul",
+            ListKind::Ordered => "ol",
+        }
+    }
+}
+
+/// How an ordered list chooses its first number
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ListStart {
+    /// `#5 item` starts counting at five
+    Number(usize),
+    /// `#_ item` continues counting from the previous list
+    Continue,
+}
+
+/// A line starting with list markers, such as `#(class) item` or `**
+/// nested item`
+#[derive(Debug, Eq, PartialEq)]
+pub struct ListLine<'a> {
+    /// The run of `*` and `#` markers, one per level of nesting
+    pub markers: &'a str,
+    pub start: Option<ListStart>,
+    pub attributes: Attributes,
+    /// Byte offset at which the item content starts
+    pub offset: usize,
+}
+
+impl ListLine<'_> {
+    pub fn depth(&self) -> usize {
+        self.markers.len()
+    }
+
+    pub fn kind(&self) -> ListKind {
+        match self.markers.as_bytes().last() {
+            Some(b'#') => ListKind::Ordered,
+            _ => ListKind::Unordered,
+        }
+    }
+}
+
+pub fn list_line(line: &str) -> Option<ListLine<'_>> {
+    let bytes = line.as_bytes();
+    let depth = bytes
+        .iter()
+        .take_while(|byte| **byte == b'*' || **byte == b'#')
+        .count();
+    if depth == 0 {
+        return None;
+    }
+    let markers = &line[..depth];
+    let mut position = depth;
+
+    let mut start = None;
+    if bytes[depth - 1] == b'#' {
+        if bytes.get(position) == Some(&b'_') {
+            start = Some(ListStart::Continue);
+            position += 1;
+        } else {
+            let digits = bytes[position..]
+                .iter()
+                .take_while(|byte| byte.is_ascii_digit())
+                .count();
+            if digits > 0 {
+                start = line[position..position + digits]
+                    .parse()
+                    .ok()
+                    .map(ListStart::Number);
+                position += digits;
+            }
+        }
+    }
+
+    let (attributes, length) = parse_phrase_attributes(&line[position..]);
+    position += length;
+
+    match bytes.get(position) {
+        Some(b' ') | Some(b'.') => position += 1,
+        _ => return None,
+    }
+    while bytes.get(position) == Some(&b' ') {
+        position += 1;
+    }
+
+    Some(ListLine {
+        markers,
+        start,
+        attributes,
+        offset: position,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn nested_mixed_markers() -> Result<()> {
+        let line = list_line("#* Item").unwrap();
+        assert_eq!("#*", line.markers);
+        assert_eq!(2, line.depth());
+        assert_eq!(ListKind::Unordered, line.kind());
+        assert_eq!(3, line.offset);
+        Ok(())
+    }
+
+    #[test]
+    fn start_numbers() -> Result<()> {
+        let line = list_line("#12(big) Item").unwrap();
+        assert_eq!(Some(ListStart::Number(12)), line.start);
+        assert_eq!(Some("big".to_string()), line.attributes.class);
+        assert_eq!(9, line.offset);
+        let line = list_line("#_ Item").unwrap();
+        assert_eq!(Some(ListStart::Continue), line.start);
+        Ok(())
+    }
+
+    #[test]
+    fn phrases_are_not_lists() -> Result<()> {
+        assert!(list_line("*strong* text").is_none());
+        assert!(list_line("**bold** text").is_none());
+        assert!(list_line("plain").is_none());
+        Ok(())
+    }
+}
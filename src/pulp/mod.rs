@@ -1,4 +1,22 @@
+mod acronyms;
+mod attributes;
+mod definitions;
+mod document;
+mod footnotes;
+mod html;
+mod images;
+mod links;
+mod lists;
+mod notes;
 mod pass_1;
 mod pass_2;
+mod pass_3;
+mod tables;
 
-pub use pass_2::{SecondPass as Parser, SecondPassEvent as Event};
+pub use attributes::Attributes;
+pub use document::Document;
+pub(crate) use links::scheme;
+pub use links::LinkAliases;
+pub use notes::NoteBacklinks;
+pub use pass_2::SecondPassEvent as Event;
+pub use pass_3::ThirdPass as Parser;
@@ -0,0 +1,106 @@
+use super::attributes::{parse_phrase_attributes, Attributes};
+
+/// Which references to a note its entry in a `notelist.` links back to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NoteBacklinks {
+    /// One link per reference, labelled `a`, `b`, `c` and so on
+    All,
+    /// `^` links to the first reference only
+    First,
+    /// `!` leaves the links out
+    None,
+}
+
+impl NoteBacklinks {
+    pub fn from_modifier(modifier: u8) -> Option<Self> {
+        match modifier {
+            b'*' => Some(NoteBacklinks::All),
+            b'^' => Some(NoteBacklinks::First),
+            b'!' => Some(NoteBacklinks::None),
+            _ => None,
+        }
+    }
+}
+
+/// A `[#label]` note reference. Ranges are byte offsets into the scanned
+/// text.
+#[derive(Debug, Eq, PartialEq)]
+pub struct NoteReference {
+    pub start: usize,
+    pub end: usize,
+    pub attributes: Attributes,
+    pub label: (usize, usize),
+    /// Whether the reference links to its note, which `[#label!]` turns
+    /// off
+    pub link: bool,
+}
+
+/// Tries to read a note reference, such as `[(class)#label]`, starting at
+/// `position`
+pub fn note_reference(text: &str, position: usize) -> Option<NoteReference> {
+    let bytes = text.as_bytes();
+    if bytes[position] != b'[' {
+        return None;
+    }
+    let (attributes, length) = parse_phrase_attributes(&text[position + 1..]);
+    let hash = position + 1 + length;
+    if bytes.get(hash) != Some(&b'#') {
+        return None;
+    }
+
+    let label = (hash + 1, hash + 1 + label_length(&text[hash + 1..]));
+    if label.0 == label.1 {
+        return None;
+    }
+    let mut end = label.1;
+    let link = bytes.get(end) != Some(&b'!');
+    if !link {
+        end += 1;
+    }
+    if bytes.get(end) != Some(&b']') {
+        return None;
+    }
+
+    Some(NoteReference {
+        start: position,
+        end: end + 1,
+        attributes,
+        label,
+        link,
+    })
+}
+
+/// Length of the note label that `text` starts with. Labels end up in
+/// ids, so they are limited to word characters and `-`.
+pub fn label_length(text: &str) -> usize {
+    text.find(|char: char| !(char.is_alphanumeric() || char == '_' || char == '-'))
+        .unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn references() -> Result<()> {
+        let text = "See[(ref)#first-note].";
+        let reference = note_reference(text, 3).unwrap();
+        assert_eq!("first-note", &text[reference.label.0..reference.label.1]);
+        assert_eq!(Some("ref".to_string()), reference.attributes.class);
+        assert!(reference.link);
+        assert_eq!(text.len() - 1, reference.end);
+
+        let reference = note_reference("[#quiet!]", 0).unwrap();
+        assert!(!reference.link);
+        Ok(())
+    }
+
+    #[test]
+    fn not_references() -> Result<()> {
+        assert!(note_reference("[#]", 0).is_none());
+        assert!(note_reference("[#two words]", 0).is_none());
+        assert!(note_reference("[1]", 0).is_none());
+        Ok(())
+    }
+}
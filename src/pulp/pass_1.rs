@@ -3,7 +3,7 @@ pub enum FirstPassEvent {
     Line(usize, usize),
     Break(usize, usize),
     NewLine(usize, usize),
-    Error(usize, usize),
+    Error,
 }
 
 pub struct FirstPass<'a> {
@@ -75,7 +75,7 @@ impl<'a> Iterator for FirstPass<'a> {
             }
 
             match delta {
-                0 => Some(FirstPassEvent::Error(start, start + delta)),
+                0 => Some(FirstPassEvent::Error),
                 1 => Some(FirstPassEvent::NewLine(start, start + delta)),
                 _ => Some(FirstPassEvent::Break(start, start + delta)),
             }
@@ -98,7 +98,7 @@ mod tests {
         assert!(matches!(pulp.next(), Some(FirstPassEvent::Line(14, 34))));
         assert!(matches!(pulp.next(), Some(FirstPassEvent::NewLine(34, 35))));
         assert!(matches!(pulp.next(), Some(FirstPassEvent::Line(35, 48))));
-        assert!(matches!(pulp.next(), None));
+        assert!(pulp.next().is_none());
         Ok(())
     }
 
@@ -114,7 +114,7 @@ mod tests {
         assert!(matches!(pulp.next(), Some(FirstPassEvent::Line(31, 37))));
         assert!(matches!(pulp.next(), Some(FirstPassEvent::NewLine(37, 38))));
         assert!(matches!(pulp.next(), Some(FirstPassEvent::Line(38, 44))));
-        assert!(matches!(pulp.next(), None));
+        assert!(pulp.next().is_none());
         Ok(())
     }
 
@@ -138,7 +138,7 @@ mod tests {
     fn ending_single_newline() -> Result<()> {
         let mut pulp = FirstPass::new("Hello\n");
         assert!(matches!(pulp.next(), Some(FirstPassEvent::Line(0, 5))));
-        assert!(matches!(pulp.next(), None));
+        assert!(pulp.next().is_none());
         Ok(())
     }
 }
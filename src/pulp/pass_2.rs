@@ -1,89 +1,988 @@
+use super::attributes::{parse_attributes, Align, Attributes};
+use super::definitions::{closing, definition_line, term_line};
+use super::footnotes::digits;
+use super::html::{block_kind, tag_at, BlockKind};
+use super::links::{alias_definition, LinkAliases};
+use super::lists::{list_line, ListKind, ListStart};
+use super::notes::{label_length, NoteBacklinks};
 use super::pass_1::{FirstPass, FirstPassEvent};
+use super::pass_3::Phrase;
+use super::tables::{
+    caption_line, cells, group_line, row_is_complete, row_start, table_signature, TableGroup,
+};
+use crate::Options;
+use std::collections::{HashMap, VecDeque};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum SecondPassEvent<'a> {
-    Paragraph,
+    Paragraph(Attributes),
     ParagraphEnd,
+    /// A block without a signature when block tags are off, which isn't
+    /// wrapped in anything
+    BareParagraph,
+    BareParagraphEnd,
+    Heading(u8, Attributes),
+    HeadingEnd(u8),
+    BlockQuote(Option<&'a str>, Attributes),
+    BlockQuoteEnd,
+    BlockCode(Attributes),
+    BlockCodeEnd,
+    Preformatted(Attributes),
+    PreformattedEnd,
+    NoTextile,
+    NoTextileEnd,
+    /// A `fn1.` footnote, with its number and whether `fn1^.` asked for a
+    /// link back to the reference
+    Footnote(&'a str, bool, Attributes),
+    FootnoteEnd,
+    /// A `note#label.` definition, which only shows up in `notelist.`s
+    NoteDefinition(&'a str, Option<NoteBacklinks>, Attributes),
+    NoteDefinitionEnd,
+    /// A `notelist:a^+.`, with the character its backlinks start counting
+    /// from, their style and whether unreferenced notes are listed too
+    NoteList(Attributes, Option<&'a str>, Option<NoteBacklinks>, bool),
+    NoteListEnd,
+    /// A block that starts with a block-level HTML tag or a comment, which
+    /// isn't wrapped in a paragraph
+    HtmlBlock,
+    HtmlBlockEnd,
+    List(ListKind, Attributes, Option<usize>),
+    ListEnd(ListKind),
+    ListItem(Attributes),
+    ListItemEnd,
+    DefinitionList(Attributes),
+    DefinitionListEnd,
+    Term(Attributes),
+    TermEnd,
+    Definition(Attributes),
+    DefinitionEnd,
+    Table(Attributes, Option<&'a str>),
+    TableEnd,
+    TableCaption(Attributes),
+    TableCaptionEnd,
+    TableGroup(TableGroup, Attributes),
+    TableGroupEnd(TableGroup),
+    TableRow(Attributes),
+    TableRowEnd,
+    TableCell(bool, Attributes),
+    TableCellEnd(bool),
+
+    Phrase(Phrase, Attributes),
+    PhraseEnd(Phrase),
+    Link(&'a str, Option<&'a str>, Attributes),
+    LinkEnd,
+    Image(&'a str, Option<&'a str>, Option<Align>, Attributes),
+    /// A `[1]` reference to a footnote, which `[1!]` keeps from linking
+    FootnoteReference(&'a str, bool),
+    NoteReference(&'a str, bool, Attributes),
+    /// An acronym with its title, around the acronym itself
+    Acronym(&'a str),
+    AcronymEnd,
+    /// A run of capitals
+    Caps(&'a str),
+
+    Text(usize, &'a str),
+    Literal(&'a str),
+    Html(&'a str),
+    LineBreak,
+}
+
+impl<'a> SecondPassEvent<'a> {
+    /// The attributes the event carries, if any
+    pub fn attributes_mut(&mut self) -> Option<&mut Attributes> {
+        match self {
+            SecondPassEvent::Paragraph(attributes)
+            | SecondPassEvent::Heading(_, attributes)
+            | SecondPassEvent::BlockQuote(_, attributes)
+            | SecondPassEvent::BlockCode(attributes)
+            | SecondPassEvent::Preformatted(attributes)
+            | SecondPassEvent::Footnote(_, _, attributes)
+            | SecondPassEvent::NoteDefinition(_, _, attributes)
+            | SecondPassEvent::NoteList(attributes, _, _, _)
+            | SecondPassEvent::List(_, attributes, _)
+            | SecondPassEvent::ListItem(attributes)
+            | SecondPassEvent::DefinitionList(attributes)
+            | SecondPassEvent::Term(attributes)
+            | SecondPassEvent::Definition(attributes)
+            | SecondPassEvent::Table(attributes, _)
+            | SecondPassEvent::TableCaption(attributes)
+            | SecondPassEvent::TableGroup(_, attributes)
+            | SecondPassEvent::TableRow(attributes)
+            | SecondPassEvent::TableCell(_, attributes)
+            | SecondPassEvent::Phrase(_, attributes)
+            | SecondPassEvent::Link(_, _, attributes)
+            | SecondPassEvent::Image(_, _, _, attributes)
+            | SecondPassEvent::NoteReference(_, _, attributes) => Some(attributes),
+            _ => None,
+        }
+    }
+}
+
+/// How the lines inside the current block are emitted
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Content {
+    Text,
+    Literal,
+    Html,
+    /// Text inside raw HTML, whose newlines are kept as they are rather
+    /// than becoming line breaks
+    Markup,
+    /// Literal text, except for the HTML tags that open and close it
+    LiteralMarkup,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Signature<'a> {
+    Heading(u8),
+    Paragraph,
+    BlockQuote(Option<&'a str>),
+    BlockCode,
+    Preformatted,
+    NoTextile,
+    Footnote(&'a str, bool),
+    Note(&'a str, Option<NoteBacklinks>),
+    NoteList(Option<&'a str>, Option<NoteBacklinks>, bool),
+}
+
+/// An explicit block signature found at the start of a block
+#[derive(Debug, PartialEq)]
+struct BlockStart<'a> {
+    signature: Signature<'a>,
+    attributes: Attributes,
+    /// Whether the signature used `..` and continues across blank lines
+    extended: bool,
+    /// Byte offset at which the block content starts
+    offset: usize,
+}
 
-    Text(usize, usize, &'a str),
-    LineBreak(usize, usize),
+/// An extended block that stays open across breaks until the next
+/// explicit block signature
+struct Extended<'a> {
+    signature: Signature<'a>,
+    attributes: Attributes,
+    /// Number of stack entries that stay open across breaks
+    depth: usize,
+    /// The break that preceded the current block, if it hasn't been
+    /// emitted yet
+    pending_break: Option<(usize, usize)>,
 }
 
 pub struct SecondPass<'a> {
     input: &'a str,
-    len: usize,
     first_pass: FirstPass<'a>,
-    current_1_event: Option<FirstPassEvent>,
-    position: usize,
+    queue: VecDeque<SecondPassEvent<'a>>,
     stack: Vec<SecondPassEvent<'a>>,
+    content: Content,
+    extended: Option<Extended<'a>>,
+    /// A newline that is only emitted once the next line turns out to
+    /// continue the current block rather than start a new list item
+    pending_newline: Option<(usize, usize)>,
+    /// Items seen so far in ordered lists, by their markers, for `#_`
+    ordered_counts: HashMap<&'a str, usize>,
+    /// Whether a `- term :=` definition is open until its `=:`, even
+    /// across blank lines
+    multiline_definition: bool,
+    /// Start of a table row that continues onto the next line because it
+    /// hasn't been closed with a `|` yet
+    pending_row: Option<usize>,
+    /// Whether raw HTML is treated as text, for restricted mode
+    restricted: bool,
+    /// Whether only paragraphs and block quotes are recognized, for lite
+    /// mode
+    lite: bool,
+    /// Whether blocks without a signature are wrapped in paragraphs
+    block_tags: bool,
+    /// Whether blocks starting with block-level HTML are kept as they are
+    raw_blocks: bool,
+    /// The `[alias]url` definitions left out of the output so far
+    link_aliases: LinkAliases<'a>,
     expecting_block: bool,
+    finished: bool,
 }
 
 impl<'a> SecondPass<'a> {
+    #[cfg(test)]
     pub fn new(input: &'a str) -> Self {
+        Self::new_with_options(input, &Options::default())
+    }
+
+    pub fn new_with_options(input: &'a str, options: &Options) -> Self {
         Self {
-            input: input,
-            len: input.len(),
+            input,
             first_pass: FirstPass::new(input),
-            current_1_event: None,
-            position: 0,
+            queue: VecDeque::new(),
             stack: vec![],
+            content: Content::Text,
+            extended: None,
+            pending_newline: None,
+            ordered_counts: HashMap::new(),
+            multiline_definition: false,
+            pending_row: None,
+            restricted: options.restricted_mode,
+            lite: options.lite_mode,
+            block_tags: options.block_tags,
+            raw_blocks: options.raw_blocks,
+            link_aliases: LinkAliases::default(),
             expecting_block: true,
+            finished: false,
+        }
+    }
+
+    /// The alias definitions seen, once the whole document is parsed
+    pub(crate) fn into_link_aliases(self) -> LinkAliases<'a> {
+        self.link_aliases
+    }
+
+    fn line(&mut self, start: usize, end: usize) {
+        if let Some((alias, url)) = self.alias_definition_at(start) {
+            self.link_aliases
+                .define(alias, start + alias.len() + 2, url);
+            return;
+        }
+        if !self.expecting_block {
+            let newline = self.pending_newline.take();
+            if self.in_list() && self.list_line(start, end) {
+                return;
+            }
+            if self.in_definition_list() {
+                if !self.definition_line(start, end, false) {
+                    self.definition_text(start, end, newline);
+                }
+                return;
+            }
+            if self.in_table() {
+                if !self.table_line(start, end, false) {
+                    self.close_all();
+                    self.open_implicit();
+                    self.content_line(start, end);
+                }
+                return;
+            }
+            if let Some((newline_start, newline_end)) = newline {
+                self.emit_newline(newline_start, newline_end);
+            }
+            self.content_line(start, end);
+            return;
+        }
+
+        self.expecting_block = false;
+        let line = &self.input[start..end];
+        let block_start = block_start(line).filter(|block_start| {
+            !self.lite
+                || matches!(
+                    block_start.signature,
+                    Signature::Paragraph | Signature::BlockQuote(_)
+                )
+        });
+        match block_start {
+            Some(block_start) => {
+                self.close_all();
+                self.open(
+                    block_start.signature.clone(),
+                    block_start.attributes.clone(),
+                );
+                if block_start.extended {
+                    self.extend(block_start.signature, block_start.attributes);
+                }
+                if start + block_start.offset < end {
+                    self.content_line(start + block_start.offset, end);
+                }
+            }
+            None => {
+                if self.multiline_definition {
+                    if !self.definition_line(start, end, false) {
+                        self.definition_text(start, end, None);
+                    }
+                } else if self.continue_extended() {
+                    self.content_line(start, end);
+                } else if let Some(kind) = block_kind(line).filter(|_| !self.restricted) {
+                    self.push(SecondPassEvent::HtmlBlock);
+                    self.content = match kind {
+                        BlockKind::Comment => Content::Html,
+                        BlockKind::Preformatted => Content::LiteralMarkup,
+                        BlockKind::Markup if self.raw_blocks => Content::Html,
+                        BlockKind::Markup => Content::Markup,
+                    };
+                    self.content_line(start, end);
+                } else if self.lite
+                    || !self.list_line(start, end)
+                        && !self.definition_line(start, end, true)
+                        && !self.table_line(start, end, true)
+                {
+                    self.open_implicit();
+                    self.content_line(start, end);
+                }
+            }
+        }
+    }
+
+    fn stack_index(&self, predicate: impl Fn(&SecondPassEvent<'a>) -> bool) -> Option<usize> {
+        self.stack.iter().rposition(predicate)
+    }
+
+    fn in_table(&self) -> bool {
+        self.stack_index(|event| matches!(event, SecondPassEvent::Table(_, _)))
+            .is_some()
+    }
+
+    /// Handles a table signature, caption, group or row line, opening the
+    /// table when `opening` a block. Returns false when the line doesn't
+    /// belong to a table.
+    fn table_line(&mut self, start: usize, end: usize, opening: bool) -> bool {
+        let line = &self.input[start..end];
+        if let Some(row_start) = self.pending_row {
+            if row_is_complete(line) {
+                self.pending_row = None;
+                self.row(row_start, end);
+            }
+            return true;
+        }
+
+        if opening {
+            if let Some(signature) = table_signature(line) {
+                let summary = signature
+                    .summary
+                    .map(|(summary_start, summary_end)| &line[summary_start..summary_end]);
+                self.push(SecondPassEvent::Table(signature.attributes, summary));
+                return true;
+            }
+            if caption_line(line).is_none()
+                && group_line(line).is_none()
+                && row_start(line).is_none()
+            {
+                return false;
+            }
+            self.push(SecondPassEvent::Table(Attributes::default(), None));
+        }
+        let Some(table) = self.stack_index(|event| matches!(event, SecondPassEvent::Table(_, _)))
+        else {
+            return false;
+        };
+
+        if let Some((attributes, offset)) = caption_line(line) {
+            self.close_to(table + 1);
+            self.push(SecondPassEvent::TableCaption(attributes));
+            let caption = line[offset..].trim_end();
+            let caption = caption.strip_suffix('|').unwrap_or(caption).trim_end();
+            if !caption.is_empty() {
+                self.content_line(start + offset, start + offset + caption.len());
+            }
+            self.close_to(table + 1);
+            return true;
+        }
+
+        if let Some((group, attributes)) = group_line(line) {
+            self.close_to(table + 1);
+            self.push(SecondPassEvent::TableGroup(group, attributes));
+            return true;
+        }
+
+        if row_start(line).is_some() {
+            if row_is_complete(line) {
+                self.row(start, end);
+            } else {
+                self.pending_row = Some(start);
+            }
+            return true;
+        }
+
+        false
+    }
+
+    /// Emits a whole table row, which may span several lines
+    fn row(&mut self, start: usize, end: usize) {
+        let row = self.input[start..end].trim_end();
+        let (attributes, offset) = row_start(row).unwrap_or_default();
+        if let Some(parent) = self.stack_index(|event| {
+            matches!(
+                event,
+                SecondPassEvent::Table(_, _) | SecondPassEvent::TableGroup(_, _)
+            )
+        }) {
+            self.close_to(parent + 1);
+        }
+
+        self.content = Content::Text;
+        self.push(SecondPassEvent::TableRow(attributes));
+        let depth = self.stack.len();
+        for cell in cells(row, offset) {
+            self.push(SecondPassEvent::TableCell(cell.header, cell.attributes));
+            let (mut line_start, cell_end) = (start + cell.content.0, start + cell.content.1);
+            while let Some(found) = self.input[line_start..cell_end].find('\n') {
+                let newline = line_start + found;
+                if line_start < newline {
+                    self.content_line(line_start, newline);
+                }
+                self.emit_newline(newline, newline + 1);
+                line_start = newline + 1;
+            }
+            if line_start < cell_end {
+                self.content_line(line_start, cell_end);
+            }
+            self.close_to(depth);
+        }
+        self.close_to(depth - 1);
+    }
+
+    /// Emits a table row that was never closed with a `|`
+    fn flush_row(&mut self, end: usize) {
+        if let Some(start) = self.pending_row.take() {
+            self.row(start, end);
+        }
+    }
+
+    fn in_definition_list(&self) -> bool {
+        self.stack_index(|event| matches!(event, SecondPassEvent::DefinitionList(_)))
+            .is_some()
+    }
+
+    /// Opens a term or definition, along with the definition list when
+    /// `opening` a block. Returns false when the line isn't part of a
+    /// definition list.
+    fn definition_line(&mut self, start: usize, end: usize, opening: bool) -> bool {
+        let line = &self.input[start..end];
+        if let Some(item) = term_line(line) {
+            // A block only becomes a definition list when it has a `:=`
+            if opening && item.definition.is_none() && !item.multiline && !item.closed {
+                return false;
+            }
+            self.open_definition_list();
+            self.push(SecondPassEvent::Term(item.attributes));
+            self.content_line(start + item.term.0, start + item.term.1);
+            self.close_definition_item();
+            if let Some((definition_start, definition_end)) = item.definition {
+                self.push(SecondPassEvent::Definition(Attributes::default()));
+                self.content_line(start + definition_start, start + definition_end);
+                if item.closed {
+                    self.close_definition_item();
+                }
+            } else if item.multiline {
+                self.push(SecondPassEvent::Definition(Attributes::default()));
+                self.multiline_definition = true;
+            }
+            return true;
+        }
+
+        if let Some(item) = definition_line(line) {
+            self.open_definition_list();
+            if item.term {
+                self.push(SecondPassEvent::Term(item.attributes));
+            } else {
+                self.push(SecondPassEvent::Definition(item.attributes));
+            }
+            if start + item.offset < end {
+                self.content_line(start + item.offset, end);
+            }
+            return true;
+        }
+
+        false
+    }
+
+    fn open_definition_list(&mut self) {
+        self.multiline_definition = false;
+        self.content = Content::Text;
+        match self.stack_index(|event| matches!(event, SecondPassEvent::DefinitionList(_))) {
+            Some(index) => self.close_to(index + 1),
+            None => self.push(SecondPassEvent::DefinitionList(Attributes::default())),
+        }
+    }
+
+    /// Closes the open term or definition, leaving the list open
+    fn close_definition_item(&mut self) {
+        self.multiline_definition = false;
+        if let Some(index) =
+            self.stack_index(|event| matches!(event, SecondPassEvent::DefinitionList(_)))
+        {
+            self.close_to(index + 1);
+        }
+    }
+
+    /// Continues the open term or definition with a line of text, which
+    /// may end it with `=:`
+    fn definition_text(&mut self, start: usize, end: usize, newline: Option<(usize, usize)>) {
+        let (text_end, closed) = closing(&self.input[..end], start);
+
+        if self.multiline_definition
+            && matches!(self.stack.last(), Some(SecondPassEvent::Definition(_)))
+        {
+            self.push(SecondPassEvent::Paragraph(Attributes::default()));
+        } else if let Some((newline_start, newline_end)) = newline {
+            self.emit_newline(newline_start, newline_end);
+        }
+
+        let text = self.input[start..text_end].trim_end();
+        if !text.is_empty() {
+            self.content_line(start, start + text.len());
+        }
+        if closed {
+            self.close_definition_item();
+        }
+    }
+
+    fn in_list(&self) -> bool {
+        self.stack
+            .iter()
+            .any(|event| matches!(event, SecondPassEvent::List(_, _, _)))
+    }
+
+    /// Opens a list item, along with any lists needed to reach its depth.
+    /// Returns false when the line isn't a list item.
+    fn list_line(&mut self, start: usize, end: usize) -> bool {
+        let Some(item) = list_line(&self.input[start..end]) else {
+            return false;
+        };
+        let depth = item.depth();
+        let kind = item.kind();
+
+        let lists: Vec<(usize, ListKind)> = self
+            .stack
+            .iter()
+            .enumerate()
+            .filter_map(|(index, event)| match event {
+                SecondPassEvent::List(kind, _, _) => Some((index, *kind)),
+                _ => None,
+            })
+            .collect();
+
+        // Levels that stay open: everything above this item's depth, plus
+        // the list at its depth when it has the same kind
+        let mut keep = lists.len().min(depth);
+        if keep == depth && lists[depth - 1].1 != kind {
+            keep -= 1;
+        }
+        if keep == depth {
+            self.close_to(lists[depth - 1].0 + 1);
+        } else if keep < lists.len() {
+            self.close_to(lists[keep].0);
+        }
+
+        let count = self.ordered_counts.entry(item.markers).or_insert(0);
+        let attributes = if keep == depth {
+            item.attributes
+        } else {
+            let start = match (kind, item.start) {
+                (ListKind::Unordered, _) => None,
+                (ListKind::Ordered, Some(ListStart::Number(number))) => {
+                    *count = number.saturating_sub(1);
+                    Some(number)
+                }
+                (ListKind::Ordered, Some(ListStart::Continue)) => Some(*count + 1),
+                (ListKind::Ordered, None) => {
+                    *count = 0;
+                    None
+                }
+            };
+            self.push(SecondPassEvent::List(kind, item.attributes, start));
+            Attributes::default()
+        };
+        if kind == ListKind::Ordered {
+            *self.ordered_counts.entry(item.markers).or_insert(0) += 1;
+        }
+
+        self.content = Content::Text;
+        self.push(SecondPassEvent::ListItem(attributes));
+        if start + item.offset < end {
+            self.content_line(start + item.offset, end);
+        }
+        true
+    }
+
+    fn extend(&mut self, signature: Signature<'a>, attributes: Attributes) {
+        let depth = match signature {
+            Signature::Heading(_) | Signature::Paragraph | Signature::Footnote(_, _) => 0,
+            _ => 1,
+        };
+        self.extended = Some(Extended {
+            signature,
+            attributes,
+            depth,
+            pending_break: None,
+        });
+    }
+
+    /// Starts a new implicit block inside the open extended block, if any
+    fn continue_extended(&mut self) -> bool {
+        let Some(extended) = self.extended.as_mut() else {
+            return false;
+        };
+
+        let pending_break = extended.pending_break.take();
+        let attributes = extended.attributes.clone();
+        match extended.signature.clone() {
+            Signature::Heading(level) => self.push(SecondPassEvent::Heading(level, attributes)),
+            Signature::Paragraph => self.push(SecondPassEvent::Paragraph(attributes)),
+            // The paragraphs after the first are the footnote's, but only
+            // the first carries its number and id
+            Signature::Footnote(_, _) => {
+                let mut attributes = attributes.without_id();
+                attributes
+                    .class
+                    .get_or_insert_with(|| "footnote".to_string());
+                self.push(SecondPassEvent::Paragraph(attributes))
+            }
+            Signature::BlockQuote(_) => {
+                self.push(SecondPassEvent::Paragraph(attributes.without_id()))
+            }
+            Signature::BlockCode | Signature::Preformatted | Signature::NoTextile => {
+                // Blank lines inside a literal extended block are kept,
+                // collapsed into a single blank line
+                if let Some((start, _)) = pending_break {
+                    let text = &self.input[start..start + 2];
+                    self.queue.push_back(match self.content {
+                        Content::Html => SecondPassEvent::Html(text),
+                        _ => SecondPassEvent::Literal(text),
+                    });
+                }
+            }
+            // Notes are never extended
+            Signature::Note(_, _) | Signature::NoteList(_, _, _) => return false,
+        }
+        true
+    }
+
+    fn content_line(&mut self, start: usize, end: usize) {
+        let text = &self.input[start..end];
+        self.queue.push_back(match self.content {
+            Content::Text | Content::Markup => SecondPassEvent::Text(start, text),
+            Content::Literal => SecondPassEvent::Literal(text),
+            Content::Html => SecondPassEvent::Html(text),
+            Content::LiteralMarkup => return self.literal_markup_line(start, end),
+        });
+    }
+
+    /// Emits a line of a `<pre>` block, keeping the tags at either end of
+    /// it as HTML and escaping everything in between
+    fn literal_markup_line(&mut self, mut start: usize, mut end: usize) {
+        let input = self.input;
+        let opening = tag_at(input, start).filter(|tag| tag.end <= end);
+        if let Some(tag) = opening {
+            self.queue
+                .push_back(SecondPassEvent::Html(&input[start..tag.end]));
+            start = tag.end;
+        }
+        let line = &input[start..end];
+        let closing = line
+            .rfind("</")
+            .filter(|found| tag_at(line, *found).is_some_and(|tag| tag.end == line.len()));
+        if let Some(found) = closing {
+            end = start + found;
+        }
+        if start < end {
+            self.queue
+                .push_back(SecondPassEvent::Literal(&input[start..end]));
+        }
+        if let Some(found) = closing {
+            let tag_start = start + found;
+            let tag_end = start + line.len();
+            self.queue
+                .push_back(SecondPassEvent::Html(&input[tag_start..tag_end]));
+        }
+    }
+
+    /// The `[alias]url` definition on the line at `start`, if any, which
+    /// is left out of the output along with the newline before it
+    fn alias_definition_at(&self, start: usize) -> Option<(&'a str, &'a str)> {
+        let rest = &self.input[start..];
+        let line = rest.find('\n').map_or(rest, |end| &rest[..end]);
+        alias_definition(line).filter(|_| self.content == Content::Text)
+    }
+
+    fn newline(&mut self, start: usize, end: usize) {
+        // Only a skipped alias definition leaves a block expected here
+        if self.expecting_block || self.alias_definition_at(end).is_some() {
+            return;
+        }
+        if self.stack.is_empty() || self.in_table() {
+            return;
+        }
+        if self.in_list() || self.in_definition_list() {
+            self.pending_newline = Some((start, end));
+            return;
+        }
+        self.emit_newline(start, end);
+    }
+
+    fn emit_newline(&mut self, start: usize, end: usize) {
+        let text = &self.input[start..end];
+        self.queue.push_back(match self.content {
+            Content::Text => SecondPassEvent::LineBreak,
+            Content::Literal | Content::LiteralMarkup => SecondPassEvent::Literal(text),
+            Content::Html | Content::Markup => SecondPassEvent::Html(text),
+        });
+    }
+
+    /// Opens the paragraph that a block without a signature makes
+    fn open_implicit(&mut self) {
+        if self.block_tags {
+            self.open(Signature::Paragraph, Attributes::default());
+        } else {
+            self.content = Content::Text;
+            self.push(SecondPassEvent::BareParagraph);
+        }
+    }
+
+    fn open(&mut self, signature: Signature<'a>, attributes: Attributes) {
+        self.content = Content::Text;
+        match signature {
+            Signature::Heading(level) => self.push(SecondPassEvent::Heading(level, attributes)),
+            Signature::Paragraph => self.push(SecondPassEvent::Paragraph(attributes)),
+            Signature::BlockQuote(cite) => {
+                let inner = attributes.without_id();
+                self.push(SecondPassEvent::BlockQuote(cite, attributes));
+                self.push(SecondPassEvent::Paragraph(inner));
+            }
+            Signature::BlockCode => {
+                self.push(SecondPassEvent::BlockCode(attributes));
+                self.content = Content::Literal;
+            }
+            Signature::Preformatted => {
+                self.push(SecondPassEvent::Preformatted(attributes));
+                self.content = Content::Literal;
+            }
+            Signature::NoTextile => {
+                self.push(SecondPassEvent::NoTextile);
+                self.content = if self.restricted {
+                    Content::Literal
+                } else {
+                    Content::Html
+                };
+            }
+            Signature::Footnote(number, backlink) => {
+                self.push(SecondPassEvent::Footnote(number, backlink, attributes))
+            }
+            Signature::Note(label, backlinks) => self.push(SecondPassEvent::NoteDefinition(
+                label, backlinks, attributes,
+            )),
+            Signature::NoteList(start, backlinks, unreferenced) => self.push(
+                SecondPassEvent::NoteList(attributes, start, backlinks, unreferenced),
+            ),
+        }
+    }
+
+    fn push(&mut self, event: SecondPassEvent<'a>) {
+        self.queue.push_back(event.clone());
+        self.stack.push(event);
+    }
+
+    /// Closes the current block, leaving an extended block open
+    fn close_block(&mut self, start: usize, end: usize) {
+        self.flush_row(start);
+        if self.multiline_definition {
+            if let Some(index) =
+                self.stack_index(|event| matches!(event, SecondPassEvent::Definition(_)))
+            {
+                self.close_to(index + 1);
+                return;
+            }
+        }
+
+        match self.extended.as_mut() {
+            Some(extended) => {
+                extended.pending_break = Some((start, end));
+                let depth = extended.depth;
+                self.close_to(depth);
+            }
+            None => self.close_to(0),
+        }
+    }
+
+    fn close_all(&mut self) {
+        self.extended = None;
+        self.multiline_definition = false;
+        self.close_to(0);
+        self.content = Content::Text;
+    }
+
+    fn close_to(&mut self, depth: usize) {
+        self.pending_newline = None;
+        while self.stack.len() > depth {
+            let Some(event) = self.stack.pop() else {
+                break;
+            };
+            if let Some(end) = end_of(&event) {
+                self.queue.push_back(end);
+            }
         }
     }
 }
 
+fn end_of<'a>(event: &SecondPassEvent<'a>) -> Option<SecondPassEvent<'a>> {
+    match event {
+        SecondPassEvent::Paragraph(_) => Some(SecondPassEvent::ParagraphEnd),
+        SecondPassEvent::BareParagraph => Some(SecondPassEvent::BareParagraphEnd),
+        SecondPassEvent::Heading(level, _) => Some(SecondPassEvent::HeadingEnd(*level)),
+        SecondPassEvent::BlockQuote(_, _) => Some(SecondPassEvent::BlockQuoteEnd),
+        SecondPassEvent::BlockCode(_) => Some(SecondPassEvent::BlockCodeEnd),
+        SecondPassEvent::Preformatted(_) => Some(SecondPassEvent::PreformattedEnd),
+        SecondPassEvent::NoTextile => Some(SecondPassEvent::NoTextileEnd),
+        SecondPassEvent::Footnote(_, _, _) => Some(SecondPassEvent::FootnoteEnd),
+        SecondPassEvent::NoteDefinition(_, _, _) => Some(SecondPassEvent::NoteDefinitionEnd),
+        SecondPassEvent::NoteList(_, _, _, _) => Some(SecondPassEvent::NoteListEnd),
+        SecondPassEvent::HtmlBlock => Some(SecondPassEvent::HtmlBlockEnd),
+        SecondPassEvent::List(kind, _, _) => Some(SecondPassEvent::ListEnd(*kind)),
+        SecondPassEvent::ListItem(_) => Some(SecondPassEvent::ListItemEnd),
+        SecondPassEvent::DefinitionList(_) => Some(SecondPassEvent::DefinitionListEnd),
+        SecondPassEvent::Term(_) => Some(SecondPassEvent::TermEnd),
+        SecondPassEvent::Definition(_) => Some(SecondPassEvent::DefinitionEnd),
+        SecondPassEvent::Table(_, _) => Some(SecondPassEvent::TableEnd),
+        SecondPassEvent::TableCaption(_) => Some(SecondPassEvent::TableCaptionEnd),
+        SecondPassEvent::TableGroup(group, _) => Some(SecondPassEvent::TableGroupEnd(*group)),
+        SecondPassEvent::TableRow(_) => Some(SecondPassEvent::TableRowEnd),
+        SecondPassEvent::TableCell(header, _) => Some(SecondPassEvent::TableCellEnd(*header)),
+        _ => None,
+    }
+}
+
+/// Recognizes an explicit block signature such as `h2. `, `bc.. `,
+/// `p(class)<. `, `fn1^. ` or `bq.:cite ` at the start of a line
+fn block_start(line: &str) -> Option<BlockStart<'_>> {
+    let bytes = line.as_bytes();
+    let (signature, mut position) = if bytes.starts_with(b"notextile") {
+        (Signature::NoTextile, 9)
+    } else if bytes.starts_with(b"notelist") {
+        (Signature::NoteList(None, None, false), 8)
+    } else if bytes.starts_with(b"note#") {
+        let end = 5 + label_length(&line[5..]);
+        if end == 5 {
+            return None;
+        }
+        let backlinks = bytes
+            .get(end)
+            .copied()
+            .and_then(NoteBacklinks::from_modifier);
+        let signature = Signature::Note(&line[5..end], backlinks);
+        (signature, end + usize::from(backlinks.is_some()))
+    } else if bytes.starts_with(b"pre") {
+        (Signature::Preformatted, 3)
+    } else if bytes.starts_with(b"bq") {
+        (Signature::BlockQuote(None), 2)
+    } else if bytes.starts_with(b"bc") {
+        (Signature::BlockCode, 2)
+    } else if bytes.starts_with(b"p") {
+        (Signature::Paragraph, 1)
+    } else if bytes.len() > 1 && bytes[0] == b'h' && (b'1'..=b'6').contains(&bytes[1]) {
+        (Signature::Heading(bytes[1] - b'0'), 2)
+    } else if bytes.starts_with(b"fn") && digits(&line[2..]) > 0 {
+        let end = 2 + digits(&line[2..]);
+        let backlink = bytes.get(end) == Some(&b'^');
+        let signature = Signature::Footnote(&line[2..end], backlink);
+        (signature, end + usize::from(backlink))
+    } else {
+        return None;
+    };
+
+    let (attributes, length) = parse_attributes(&line[position..]);
+    position += length;
+
+    let signature = match signature {
+        Signature::NoteList(_, _, _) => {
+            let (signature, length) = note_list(&line[position..]);
+            position += length;
+            signature
+        }
+        signature => signature,
+    };
+
+    if bytes.get(position) != Some(&b'.') {
+        return None;
+    }
+    position += 1;
+
+    let extended = bytes.get(position) == Some(&b'.');
+    if extended {
+        if matches!(
+            signature,
+            Signature::Note(_, _) | Signature::NoteList(_, _, _)
+        ) {
+            return None;
+        }
+        position += 1;
+    }
+
+    let signature = match signature {
+        Signature::BlockQuote(_) if bytes.get(position) == Some(&b':') => {
+            let cite_start = position + 1;
+            let cite_end = line[cite_start..]
+                .find(char::is_whitespace)
+                .map_or(line.len(), |offset| cite_start + offset);
+            if cite_start == cite_end {
+                return None;
+            }
+            position = cite_end;
+            Signature::BlockQuote(Some(&line[cite_start..cite_end]))
+        }
+        signature => signature,
+    };
+
+    let offset = match bytes.get(position) {
+        None => position,
+        Some(b' ') | Some(b'\t') => position + 1,
+        Some(_) => return None,
+    };
+
+    Some(BlockStart {
+        signature,
+        attributes,
+        extended,
+        offset,
+    })
+}
+
+/// Reads the `:a^+` after the attributes of a `notelist`
+fn note_list(text: &str) -> (Signature<'_>, usize) {
+    let mut position = 0;
+    let mut start = None;
+    if let Some(rest) = text.strip_prefix(':') {
+        if let Some(char) = rest.chars().next().filter(|char| *char != '.') {
+            start = Some(&rest[..char.len_utf8()]);
+            position = 1 + char.len_utf8();
+        }
+    }
+    let backlinks = text
+        .as_bytes()
+        .get(position)
+        .filter(|modifier| **modifier != b'*')
+        .and_then(|modifier| NoteBacklinks::from_modifier(*modifier));
+    if backlinks.is_some() {
+        position += 1;
+    }
+    let unreferenced = text.as_bytes().get(position) == Some(&b'+');
+    if unreferenced {
+        position += 1;
+    }
+    (
+        Signature::NoteList(start, backlinks, unreferenced),
+        position,
+    )
+}
+
 impl<'a> Iterator for SecondPass<'a> {
     type Item = SecondPassEvent<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Pull the next FirstPassNode
-        if self.current_1_event.is_none() {
-            self.current_1_event = self.first_pass.next();
-            match self.current_1_event {
-                None => self.position = self.len,
-                Some(FirstPassEvent::Line(start, _)) => self.position = start,
-                Some(FirstPassEvent::Break(start, _)) => self.position = start,
-                Some(FirstPassEvent::NewLine(start, _)) => self.position = start,
-                Some(FirstPassEvent::Error(start, _)) => self.position = start,
-            }
-        }
-
-        // Handle the end of the document
-        if self.current_1_event.is_none() && !self.stack.is_empty() {
-            self.current_1_event = Some(FirstPassEvent::Break(self.len, self.len))
-        }
-
-        match self.current_1_event {
-            None => return None,
-            Some(FirstPassEvent::Line(start, end)) => {
-                if self.expecting_block {
-                    self.expecting_block = false;
-                    self.stack.push(SecondPassEvent::Paragraph);
-                    return Some(SecondPassEvent::Paragraph);
-                }
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Some(event);
+            }
 
-                self.current_1_event = None;
-                return Some(SecondPassEvent::Text(start, end, &self.input[start..end]));
-            }
-            Some(FirstPassEvent::Break(_, _)) => {
-                self.current_1_event = None;
-                self.expecting_block = true;
-                match self.stack.pop() {
-                    None => return self.next(),
-                    Some(SecondPassEvent::Paragraph) => return Some(SecondPassEvent::ParagraphEnd),
-                    Some(SecondPassEvent::ParagraphEnd) => return self.next(),
-                    Some(SecondPassEvent::Text(_, _, _)) => return self.next(),
-                    Some(SecondPassEvent::LineBreak(_, _)) => return self.next(),
+            match self.first_pass.next() {
+                None => {
+                    // Handle the end of the document
+                    if self.finished {
+                        return None;
+                    }
+                    self.finished = true;
+                    self.flush_row(self.input.len());
+                    self.close_all();
                 }
-            }
-            Some(FirstPassEvent::NewLine(start, end)) => {
-                self.current_1_event = None;
-                return Some(SecondPassEvent::LineBreak(start, end));
-            }
-            Some(FirstPassEvent::Error(_, _)) => {
-                self.current_1_event = None;
-                return self.next();
+                Some(FirstPassEvent::Line(start, end)) => self.line(start, end),
+                Some(FirstPassEvent::Break(start, end)) => {
+                    self.expecting_block = true;
+                    self.close_block(start, end);
+                }
+                Some(FirstPassEvent::NewLine(start, end)) => self.newline(start, end),
+                Some(FirstPassEvent::Error) => {}
             }
         }
     }
@@ -97,53 +996,511 @@ mod tests {
     #[test]
     fn implicit_paragraph() -> Result<()> {
         let mut pulp = SecondPass::new("A paragraph");
-        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
         assert!(matches!(
             pulp.next(),
-            Some(SecondPassEvent::Text(0, 11, "A paragraph"))
+            Some(SecondPassEvent::Text(0, "A paragraph"))
         ));
         assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
-        assert!(matches!(pulp.next(), None));
+        assert!(pulp.next().is_none());
         Ok(())
     }
 
     #[test]
     fn implicit_paragraphs() -> Result<()> {
         let mut pulp = SecondPass::new("Paragraph 1\n\nParagraph 2");
-        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
         assert!(matches!(
             pulp.next(),
-            Some(SecondPassEvent::Text(0, 11, "Paragraph 1"))
+            Some(SecondPassEvent::Text(0, "Paragraph 1"))
         ));
         assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
-        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
         assert!(matches!(
             pulp.next(),
-            Some(SecondPassEvent::Text(13, 24, "Paragraph 2"))
+            Some(SecondPassEvent::Text(13, "Paragraph 2"))
         ));
         assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
-        assert!(matches!(pulp.next(), None));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn alias_definitions_are_skipped() -> Result<()> {
+        let mut pulp = SecondPass::new("[a]/a\nText\n[b]http://b.example/\nmore");
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(6, "Text"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::LineBreak)));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(32, "more"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
+        assert!(pulp.next().is_none());
         Ok(())
     }
 
     #[test]
     fn paragraph_and_not_a_paragraph() -> Result<()> {
         let mut pulp = SecondPass::new("Paragraph and newline\np. with fake");
-        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(0, "Paragraph and newline"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::LineBreak)));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(22, "p. with fake"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn explicit_heading() -> Result<()> {
+        let mut pulp = SecondPass::new("h2. Title\n\np. Body");
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Heading(2, _))));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(4, "Title"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::HeadingEnd(2))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(14, "Body"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn block_quote_with_cite() -> Result<()> {
+        let mut pulp = SecondPass::new("bq.:http://example.com Quoted");
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::BlockQuote(Some("http://example.com"), _))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(23, "Quoted"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::BlockQuoteEnd)));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn block_code_is_literal() -> Result<()> {
+        let mut pulp = SecondPass::new("bc. a\nb");
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::BlockCode(_))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Literal("a"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Literal("\n"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Literal("b"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::BlockCodeEnd)));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn extended_block_code() -> Result<()> {
+        let mut pulp = SecondPass::new("bc.. a\n\n\nb\n\np. c");
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::BlockCode(_))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Literal("a"))));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Literal("\n\n"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Literal("b"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::BlockCodeEnd)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Text(15, "c"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn extended_block_quote() -> Result<()> {
+        let mut pulp = SecondPass::new("bq.. a\n\nb");
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::BlockQuote(None, _))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Text(_, "a"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Text(_, "b"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::BlockQuoteEnd)));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn extended_footnote() -> Result<()> {
+        let mut pulp =
+            SecondPass::new("fn2.. two\n\nmore two\n\nfn3(note#n).. three\n\nmore three");
         assert!(matches!(
             pulp.next(),
-            Some(SecondPassEvent::Text(0, 21, "Paragraph and newline"))
+            Some(SecondPassEvent::Footnote("2", false, _))
         ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Text(_, "two"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::FootnoteEnd)));
+        match pulp.next() {
+            Some(SecondPassEvent::Paragraph(attributes)) => {
+                assert_eq!(Some("footnote".to_string()), attributes.class);
+                assert_eq!(None, attributes.id);
+            }
+            event => panic!("expected a paragraph, got {:?}", event),
+        }
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(_, "more two"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
         assert!(matches!(
             pulp.next(),
-            Some(SecondPassEvent::LineBreak(21, 22))
+            Some(SecondPassEvent::Footnote("3", false, _))
         ));
         assert!(matches!(
             pulp.next(),
-            Some(SecondPassEvent::Text(22, 34, "p. with fake"))
+            Some(SecondPassEvent::Text(_, "three"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::FootnoteEnd)));
+        match pulp.next() {
+            Some(SecondPassEvent::Paragraph(attributes)) => {
+                assert_eq!(Some("note".to_string()), attributes.class);
+                assert_eq!(None, attributes.id);
+            }
+            event => panic!("expected a paragraph, got {:?}", event),
+        }
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(_, "more three"))
         ));
         assert!(matches!(pulp.next(), Some(SecondPassEvent::ParagraphEnd)));
-        assert!(matches!(pulp.next(), None));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn block_start_requires_space() -> Result<()> {
+        let heading = block_start("h1. Title").unwrap();
+        assert_eq!(Signature::Heading(1), heading.signature);
+        assert_eq!(4, heading.offset);
+        let extended = block_start("pre.. x").unwrap();
+        assert_eq!(Signature::Preformatted, extended.signature);
+        assert!(extended.extended);
+        assert_eq!(6, extended.offset);
+        assert!(block_start("p.with").is_none());
+        assert!(block_start("h7. Title").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn block_start_with_attributes() -> Result<()> {
+        let block_start = block_start("bq(quote#q1)[en]>.:http://example.com Text").unwrap();
+        assert_eq!(
+            Signature::BlockQuote(Some("http://example.com")),
+            block_start.signature
+        );
+        assert_eq!(Some("quote".to_string()), block_start.attributes.class);
+        assert_eq!(Some("q1".to_string()), block_start.attributes.id);
+        assert_eq!(Some("en".to_string()), block_start.attributes.lang);
+        assert_eq!(Some(Align::Right), block_start.attributes.align);
+        assert_eq!(38, block_start.offset);
+        Ok(())
+    }
+
+    #[test]
+    fn footnote_signature() -> Result<()> {
+        let block_start = block_start("fn12^(note). Text").unwrap();
+        assert_eq!(Signature::Footnote("12", true), block_start.signature);
+        assert_eq!(Some("note".to_string()), block_start.attributes.class);
+        assert_eq!(13, block_start.offset);
+        assert!(super::block_start("fn. Text").is_none());
+        assert!(super::block_start("fnord. Text").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn note_signatures() -> Result<()> {
+        let block_start = super::block_start("note#intro^(aside). Text").unwrap();
+        assert_eq!(
+            Signature::Note("intro", Some(NoteBacklinks::First)),
+            block_start.signature
+        );
+        assert_eq!(Some("aside".to_string()), block_start.attributes.class);
+
+        let block_start = super::block_start("notelist(notes):1!+.").unwrap();
+        assert_eq!(
+            Signature::NoteList(Some("1"), Some(NoteBacklinks::None), true),
+            block_start.signature
+        );
+        assert_eq!(Some("notes".to_string()), block_start.attributes.class);
+        assert!(super::block_start("note#. Text").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn html_blocks() -> Result<()> {
+        let pulp = SecondPass::new("<div>\n*a*\n\n<pre>\"b\"\n</pre>\n\n<!-- c -->");
+        let events: Vec<String> = pulp
+            .map(|event| match event {
+                SecondPassEvent::HtmlBlock => "block".to_string(),
+                SecondPassEvent::HtmlBlockEnd => "/block".to_string(),
+                SecondPassEvent::Text(_, text) => format!("text {}", text),
+                SecondPassEvent::Literal(text) => format!("literal {:?}", text),
+                SecondPassEvent::Html(html) => format!("html {:?}", html),
+                event => format!("{:?}", event),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "block",
+                "text <div>",
+                "html \"\\n\"",
+                "text *a*",
+                "/block",
+                "block",
+                "html \"<pre>\"",
+                "literal \"\\\"b\\\"\"",
+                "literal \"\\n\"",
+                "html \"</pre>\"",
+                "/block",
+                "block",
+                "html \"<!-- c -->\"",
+                "/block"
+            ],
+            events
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lite_mode() -> Result<()> {
+        let options = Options {
+            lite_mode: true,
+            ..Default::default()
+        };
+        let pulp = SecondPass::new_with_options("h1. a\n\nbq. b\n\n* c\n|d|", &options);
+        let events: Vec<String> = pulp
+            .map(|event| match event {
+                SecondPassEvent::Paragraph(_) => "p".to_string(),
+                SecondPassEvent::BlockQuote(_, _) => "bq".to_string(),
+                SecondPassEvent::Text(_, text) => text.to_string(),
+                SecondPassEvent::LineBreak => "br".to_string(),
+                SecondPassEvent::ParagraphEnd => "/p".to_string(),
+                SecondPassEvent::BlockQuoteEnd => "/bq".to_string(),
+                event => format!("{:?}", event),
+            })
+            .collect();
+        assert_eq!(
+            vec!["p", "h1. a", "/p", "bq", "p", "b", "/p", "/bq", "p", "* c", "br", "|d|", "/p"],
+            events
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn raw_blocks() -> Result<()> {
+        let options = Options {
+            raw_blocks: true,
+            ..Default::default()
+        };
+        let mut pulp = SecondPass::new_with_options("<div>*a*\n</div>", &options);
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::HtmlBlock)));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Html("<div>*a*"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Html("\n"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Html("</div>"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::HtmlBlockEnd)));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn bare_paragraphs() -> Result<()> {
+        let options = Options {
+            block_tags: false,
+            ..Default::default()
+        };
+        let mut pulp = SecondPass::new_with_options("a\n\np. b", &options);
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::BareParagraph)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Text(0, "a"))));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::BareParagraphEnd)
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Paragraph(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn nested_list() -> Result<()> {
+        let mut pulp = SecondPass::new("* A\n** A1\n* B\nmore");
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::List(ListKind::Unordered, _, None))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ListItem(_))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Text(2, "A"))));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::List(ListKind::Unordered, _, None))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ListItem(_))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Text(7, "A1"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ListItemEnd)));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::ListEnd(ListKind::Unordered))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ListItemEnd)));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ListItem(_))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::Text(12, "B"))));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::LineBreak)));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::Text(14, "more"))
+        ));
+        assert!(matches!(pulp.next(), Some(SecondPassEvent::ListItemEnd)));
+        assert!(matches!(
+            pulp.next(),
+            Some(SecondPassEvent::ListEnd(ListKind::Unordered))
+        ));
+        assert!(pulp.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn continued_numbering() -> Result<()> {
+        let pulp = SecondPass::new("# a\n# b\n\npara\n\n#_ c\n\n#7 d");
+        let starts: Vec<Option<usize>> = pulp
+            .filter_map(|event| match event {
+                SecondPassEvent::List(ListKind::Ordered, _, start) => Some(start),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![None, Some(3), Some(7)], starts);
+        Ok(())
+    }
+
+    #[test]
+    fn definition_list() -> Result<()> {
+        let pulp = SecondPass::new("; coffee\n: hot\n: black");
+        let events: Vec<&str> = pulp
+            .map(|event| match event {
+                SecondPassEvent::DefinitionList(_) => "dl",
+                SecondPassEvent::Term(_) => "dt",
+                SecondPassEvent::Definition(_) => "dd",
+                SecondPassEvent::Text(_, text) => text,
+                SecondPassEvent::DefinitionListEnd => "/dl",
+                SecondPassEvent::TermEnd => "/dt",
+                SecondPassEvent::DefinitionEnd => "/dd",
+                _ => "?",
+            })
+            .collect();
+        assert_eq!(
+            vec!["dl", "dt", "coffee", "/dt", "dd", "hot", "/dd", "dd", "black", "/dd", "/dl"],
+            events
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn multiline_definition() -> Result<()> {
+        let pulp = SecondPass::new("- tea :=\none\n\ntwo =:\n- milk := white");
+        let events: Vec<&str> = pulp
+            .map(|event| match event {
+                SecondPassEvent::DefinitionList(_) => "dl",
+                SecondPassEvent::Term(_) => "dt",
+                SecondPassEvent::Definition(_) => "dd",
+                SecondPassEvent::Paragraph(_) => "p",
+                SecondPassEvent::Text(_, text) => text,
+                SecondPassEvent::DefinitionListEnd => "/dl",
+                SecondPassEvent::TermEnd => "/dt",
+                SecondPassEvent::DefinitionEnd => "/dd",
+                SecondPassEvent::ParagraphEnd => "/p",
+                _ => "?",
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "dl", "dt", "tea", "/dt", "dd", "p", "one", "/p", "p", "two", "/p", "/dd", "dt",
+                "milk", "/dt", "dd", "white", "/dd", "/dl"
+            ],
+            events
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn table() -> Result<()> {
+        let pulp =
+            SecondPass::new("table(grid).\n|=. Caption\n|^.\n|_. a|_. b|\n|-.\n|1|two\nlines|");
+        let events: Vec<String> = pulp
+            .map(|event| match event {
+                SecondPassEvent::Table(attributes, _) => format!("table {:?}", attributes.class),
+                SecondPassEvent::TableCaption(_) => "caption".to_string(),
+                SecondPassEvent::TableGroup(group, _) => group.tag().to_string(),
+                SecondPassEvent::TableRow(_) => "tr".to_string(),
+                SecondPassEvent::TableCell(true, _) => "th".to_string(),
+                SecondPassEvent::TableCell(false, _) => "td".to_string(),
+                SecondPassEvent::Text(_, text) => text.to_string(),
+                SecondPassEvent::LineBreak => "br".to_string(),
+                SecondPassEvent::TableEnd => "/table".to_string(),
+                SecondPassEvent::TableCaptionEnd => "/caption".to_string(),
+                SecondPassEvent::TableGroupEnd(group) => format!("/{}", group.tag()),
+                SecondPassEvent::TableRowEnd => "/tr".to_string(),
+                SecondPassEvent::TableCellEnd(_) => "/cell".to_string(),
+                event => format!("{:?}", event),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "table Some(\"grid\")",
+                "caption",
+                "Caption",
+                "/caption",
+                "thead",
+                "tr",
+                "th",
+                "a",
+                "/cell",
+                "th",
+                "b",
+                "/cell",
+                "/tr",
+                "/thead",
+                "tbody",
+                "tr",
+                "td",
+                "1",
+                "/cell",
+                "td",
+                "two",
+                "br",
+                "lines",
+                "/cell",
+                "/tr",
+                "/tbody",
+                "/table"
+            ],
+            events
+        );
         Ok(())
     }
 }
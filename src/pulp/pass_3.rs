@@ -0,0 +1,626 @@
+use super::acronyms::{acronym_at, caps_at};
+use super::attributes::{parse_phrase_attributes, Attributes};
+use super::document::Document;
+use super::footnotes::footnote_reference;
+use super::html::{closing_tag, comment_at, tag_at};
+use super::images::image_at;
+use super::links::{is_safe_url, link_at, self_link_text, LinkAliases};
+use super::notes::note_reference;
+use super::pass_2::{SecondPass, SecondPassEvent};
+use crate::Options;
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phrase {
+    Strong,
+    Bold,
+    Emphasis,
+    Italic,
+    Citation,
+    Deleted,
+    Inserted,
+    Superscript,
+    Subscript,
+    Span,
+    Code,
+}
+
+impl Phrase {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Phrase::Strong => "strong",
+            Phrase::Bold => "b",
+            Phrase::Emphasis => "em",
+            Phrase::Italic => "i",
+            Phrase::Citation => "cite",
+            Phrase::Deleted => "del",
+            Phrase::Inserted => "ins",
+            Phrase::Superscript => "sup",
+            Phrase::Subscript => "sub",
+            Phrase::Span => "span",
+            Phrase::Code => "code",
+        }
+    }
+
+    /// The modifier that opens and closes the phrase, longest first
+    fn modifier(&self) -> &'static str {
+        match self {
+            Phrase::Strong => "*",
+            Phrase::Bold => "**",
+            Phrase::Emphasis => "_",
+            Phrase::Italic => "__",
+            Phrase::Citation => "??",
+            Phrase::Deleted => "-",
+            Phrase::Inserted => "+",
+            Phrase::Superscript => "^",
+            Phrase::Subscript => "~",
+            Phrase::Span => "%",
+            Phrase::Code => "@",
+        }
+    }
+}
+
+const PHRASES: [Phrase; 11] = [
+    Phrase::Bold,
+    Phrase::Italic,
+    Phrase::Citation,
+    Phrase::Strong,
+    Phrase::Emphasis,
+    Phrase::Deleted,
+    Phrase::Inserted,
+    Phrase::Superscript,
+    Phrase::Subscript,
+    Phrase::Span,
+    Phrase::Code,
+];
+
+/// Splits the `Text` events of the second pass into inline events
+pub struct ThirdPass<'a> {
+    second_pass: SecondPass<'a>,
+    document: Document<'a>,
+    queue: VecDeque<SecondPassEvent<'a>>,
+    restricted: bool,
+    images: bool,
+}
+
+impl<'a> ThirdPass<'a> {
+    pub fn new_with_options(input: &'a str, options: &Options) -> Self {
+        Self {
+            second_pass: SecondPass::new_with_options(input, options),
+            document: Document::new(input, options),
+            queue: VecDeque::new(),
+            restricted: options.restricted_mode,
+            images: options.images_enabled(),
+        }
+    }
+
+    /// What the events may refer to from elsewhere in the document
+    pub fn document(&self) -> &Document<'a> {
+        &self.document
+    }
+}
+
+impl<'a> Iterator for ThirdPass<'a> {
+    type Item = SecondPassEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut event = loop {
+            if let Some(event) = self.queue.pop_front() {
+                break event;
+            }
+
+            match self.second_pass.next()? {
+                SecondPassEvent::Text(start, text) => {
+                    let context = Context {
+                        aliases: &self.document.link_aliases,
+                        restricted: self.restricted,
+                        images: self.images,
+                    };
+                    inline(text, start, &context, &mut self.queue)
+                }
+                event => break event,
+            }
+        };
+        if self.restricted {
+            if let Some(attributes) = event.attributes_mut() {
+                *attributes = attributes.restricted();
+            }
+        }
+        Some(event)
+    }
+}
+
+/// What inline markup depends on besides the text it's found in
+struct Context<'c, 'a> {
+    aliases: &'c LinkAliases<'a>,
+    /// Whether raw HTML is left as text and only safe URLs are linked
+    restricted: bool,
+    images: bool,
+}
+
+impl Context<'_, '_> {
+    /// Whether a link or image may point at `href`, resolving aliases
+    fn allows(&self, href: &str) -> bool {
+        !self.restricted || is_safe_url(self.aliases.get(href).unwrap_or(href))
+    }
+}
+
+/// A phrase found in a text span. Positions are relative to the span.
+#[derive(Debug)]
+struct Span {
+    phrase: Phrase,
+    start: usize,
+    content_start: usize,
+    content_end: usize,
+    end: usize,
+}
+
+/// Scans `text`, found at byte `offset` of the document, for phrase
+/// modifiers and pushes the resulting events
+fn inline<'a>(
+    text: &'a str,
+    offset: usize,
+    context: &Context<'_, 'a>,
+    events: &mut VecDeque<SecondPassEvent<'a>>,
+) {
+    let mut position = 0;
+    let mut plain = 0;
+
+    while position < text.len() {
+        if let Some(end) = comment_at(text, position).filter(|_| !context.restricted) {
+            push_text(text, offset, plain, position, events);
+            push_html(text, position, end, events);
+
+            position = end;
+            plain = end;
+            continue;
+        }
+
+        if let Some(tag) = tag_at(text, position).filter(|_| !context.restricted) {
+            push_text(text, offset, plain, position, events);
+            push_html(text, position, tag.end, events);
+            let mut end = tag.end;
+            // The content of an inline `<code>` is shown as it is
+            if !tag.closing && tag.name.eq_ignore_ascii_case("code") {
+                if let Some(close) = closing_tag(text, tag.end, "code") {
+                    if tag.end < close {
+                        let content = &text[tag.end..close];
+                        events.push_back(SecondPassEvent::Literal(content));
+                    }
+                    end = tag_at(text, close).map_or(text.len(), |tag| tag.end);
+                    push_html(text, close, end, events);
+                }
+            }
+
+            position = end;
+            plain = end;
+            continue;
+        }
+
+        if let Some(reference) = footnote_reference(text, position) {
+            push_text(text, offset, plain, reference.start, events);
+            let (start, end) = reference.number;
+            events.push_back(SecondPassEvent::FootnoteReference(
+                &text[start..end],
+                reference.link,
+            ));
+
+            position = reference.end;
+            plain = reference.end;
+            continue;
+        }
+
+        if let Some(reference) = note_reference(text, position) {
+            push_text(text, offset, plain, reference.start, events);
+            let (start, end) = reference.label;
+            events.push_back(SecondPassEvent::NoteReference(
+                &text[start..end],
+                reference.link,
+                reference.attributes,
+            ));
+
+            position = reference.end;
+            plain = reference.end;
+            continue;
+        }
+
+        let image = image_at(text, position)
+            .filter(|_| context.images)
+            .filter(|image| context.allows(&text[image.src.0..image.src.1]))
+            .filter(|image| {
+                image
+                    .href
+                    .is_none_or(|(start, end)| context.allows(&text[start..end]))
+            });
+        if let Some(image) = image {
+            push_text(text, offset, plain, image.start, events);
+            let href = image.href.map(|(start, end)| &text[start..end]);
+            if let Some(href) = href {
+                events.push_back(SecondPassEvent::Link(href, None, Attributes::default()));
+            }
+            events.push_back(SecondPassEvent::Image(
+                &text[image.src.0..image.src.1],
+                image.alt.map(|(start, end)| &text[start..end]),
+                image.align,
+                image.attributes,
+            ));
+            if href.is_some() {
+                events.push_back(SecondPassEvent::LinkEnd);
+            }
+
+            position = image.end;
+            plain = image.end;
+            continue;
+        }
+
+        let link =
+            link_at(text, position).filter(|link| context.allows(&text[link.href.0..link.href.1]));
+        if let Some(link) = link {
+            push_text(text, offset, plain, link.start, events);
+            let href = &text[link.href.0..link.href.1];
+            let title = link.title.map(|(start, end)| &text[start..end]);
+            events.push_back(SecondPassEvent::Link(href, title, link.attributes));
+            let (start, end) = link.text;
+            if &text[start..end] == "$" {
+                let (url_start, url) = context
+                    .aliases
+                    .locate(href)
+                    .unwrap_or((offset + link.href.0, href));
+                let shown = self_link_text(url);
+                let shown_start = url_start + url.len() - shown.len();
+                events.push_back(SecondPassEvent::Text(shown_start, shown));
+            } else {
+                inline(&text[start..end], offset + start, context, events);
+            }
+            events.push_back(SecondPassEvent::LinkEnd);
+
+            position = link.end;
+            plain = link.end;
+            continue;
+        }
+
+        if let Some(acronym) = acronym_at(text, position) {
+            push_text(text, offset, plain, acronym.start, events);
+            let (start, end) = acronym.content;
+            let content = &text[start..end];
+            events.push_back(SecondPassEvent::Acronym(
+                &text[acronym.title.0..acronym.title.1],
+            ));
+            if caps_at(content, 0) == Some(content.len()) {
+                events.push_back(SecondPassEvent::Caps(content));
+            } else {
+                push_text(text, offset, start, end, events);
+            }
+            events.push_back(SecondPassEvent::AcronymEnd);
+
+            position = acronym.end;
+            plain = acronym.end;
+            continue;
+        }
+
+        if let Some(end) = caps_at(text, position) {
+            push_text(text, offset, plain, position, events);
+            events.push_back(SecondPassEvent::Caps(&text[position..end]));
+
+            position = end;
+            plain = end;
+            continue;
+        }
+
+        let Some((span, attributes)) = span_at(text, position) else {
+            position += text[position..].chars().next().map_or(1, char::len_utf8);
+            continue;
+        };
+
+        push_text(text, offset, plain, span.start, events);
+        events.push_back(SecondPassEvent::Phrase(span.phrase, attributes));
+        let content = &text[span.content_start..span.content_end];
+        let content_offset = offset + span.content_start;
+        if span.phrase == Phrase::Code {
+            events.push_back(SecondPassEvent::Literal(content));
+        } else {
+            inline(content, content_offset, context, events);
+        }
+        events.push_back(SecondPassEvent::PhraseEnd(span.phrase));
+
+        position = span.end;
+        plain = span.end;
+    }
+
+    push_text(text, offset, plain, text.len(), events);
+}
+
+fn push_text<'a>(
+    text: &'a str,
+    offset: usize,
+    start: usize,
+    end: usize,
+    events: &mut VecDeque<SecondPassEvent<'a>>,
+) {
+    if start < end {
+        events.push_back(SecondPassEvent::Text(offset + start, &text[start..end]));
+    }
+}
+
+fn push_html<'a>(
+    text: &'a str,
+    start: usize,
+    end: usize,
+    events: &mut VecDeque<SecondPassEvent<'a>>,
+) {
+    events.push_back(SecondPassEvent::Html(&text[start..end]));
+}
+
+/// Tries to read a phrase starting at `position`, either bare (`*text*`)
+/// or bracketed (`[*text*]`)
+fn span_at(text: &str, position: usize) -> Option<(Span, Attributes)> {
+    let bytes = text.as_bytes();
+    let bracketed = bytes[position] == b'[';
+    let open = if bracketed { position + 1 } else { position };
+
+    if !bracketed && !at_boundary(text, position) {
+        return None;
+    }
+
+    let phrase = PHRASES
+        .into_iter()
+        .find(|phrase| text[open..].starts_with(phrase.modifier()))?;
+    let modifier = phrase.modifier();
+    let after_modifier = open + modifier.len();
+    if bytes.get(after_modifier) == Some(&modifier.as_bytes()[0]) {
+        return None;
+    }
+
+    let attributes_length = match phrase {
+        Phrase::Code => 0,
+        _ => parse_phrase_attributes(&text[after_modifier..]).1,
+    };
+    if attributes_length > 0 {
+        if let Some(span) = close(text, phrase, position, after_modifier + attributes_length) {
+            let (attributes, _) = parse_phrase_attributes(&text[after_modifier..]);
+            return Some((span, attributes));
+        }
+    }
+
+    let span = close(text, phrase, position, after_modifier)?;
+    Some((span, Attributes::default()))
+}
+
+/// Whether inline markup may start at `position`, rather than in the
+/// middle of a word
+pub fn at_boundary(text: &str, position: usize) -> bool {
+    match text[..position].chars().next_back() {
+        None => true,
+        Some(previous) => previous.is_whitespace() || previous.is_ascii_punctuation(),
+    }
+}
+
+/// Finds the closing modifier of a phrase whose content starts at
+/// `content_start`
+fn close(text: &str, phrase: Phrase, start: usize, content_start: usize) -> Option<Span> {
+    let bytes = text.as_bytes();
+    let bracketed = bytes[start] == b'[';
+    let modifier = phrase.modifier();
+
+    let first = text[content_start..].chars().next()?;
+    if first.is_whitespace() && phrase != Phrase::Code {
+        return None;
+    }
+
+    let mut search = content_start + first.len_utf8();
+    while let Some(found) = text[search..].find(modifier) {
+        let content_end = search + found;
+        search = content_end + modifier.len();
+
+        let last = text[..content_end].chars().next_back()?;
+        if last.is_whitespace() && phrase != Phrase::Code {
+            continue;
+        }
+        if modifier.len() == 1 && bytes.get(search) == Some(&modifier.as_bytes()[0]) {
+            continue;
+        }
+
+        let end = if bracketed {
+            if bytes.get(search) != Some(&b']') {
+                continue;
+            }
+            search + 1
+        } else {
+            if !closes(&text[search..]) {
+                continue;
+            }
+            search
+        };
+
+        return Some(Span {
+            phrase,
+            start,
+            content_start,
+            content_end,
+            end,
+        });
+    }
+
+    None
+}
+
+/// Whether the text after a closing modifier allows the phrase to end
+/// there, so that modifiers inside words aren't treated as phrases
+fn closes(rest: &str) -> bool {
+    let mut chars = rest.chars();
+    match chars.next() {
+        None => true,
+        Some(char) if char.is_whitespace() => true,
+        Some(char) if char.is_ascii_punctuation() => !chars
+            .find(|char| !char.is_ascii_punctuation())
+            .is_some_and(|char| char.is_ascii_digit()),
+        Some(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    fn events(text: &str) -> Vec<SecondPassEvent<'_>> {
+        let mut events = VecDeque::new();
+        let context = Context {
+            aliases: &LinkAliases::default(),
+            restricted: false,
+            images: true,
+        };
+        inline(text, 0, &context, &mut events);
+        events.into()
+    }
+
+    #[test]
+    fn strong_and_emphasis() -> Result<()> {
+        let events = events("a *b* _c_");
+        assert!(matches!(events[0], SecondPassEvent::Text(0, "a ")));
+        assert!(matches!(
+            events[1],
+            SecondPassEvent::Phrase(Phrase::Strong, _)
+        ));
+        assert!(matches!(events[2], SecondPassEvent::Text(3, "b")));
+        assert!(matches!(
+            events[3],
+            SecondPassEvent::PhraseEnd(Phrase::Strong)
+        ));
+        assert!(matches!(events[4], SecondPassEvent::Text(5, " ")));
+        assert!(matches!(
+            events[5],
+            SecondPassEvent::Phrase(Phrase::Emphasis, _)
+        ));
+        assert!(matches!(events[6], SecondPassEvent::Text(7, "c")));
+        assert_eq!(8, events.len());
+        Ok(())
+    }
+
+    #[test]
+    fn nested_phrases() -> Result<()> {
+        let events = events("**bold _and em_**");
+        assert!(matches!(
+            events[0],
+            SecondPassEvent::Phrase(Phrase::Bold, _)
+        ));
+        assert!(matches!(events[1], SecondPassEvent::Text(2, "bold ")));
+        assert!(matches!(
+            events[2],
+            SecondPassEvent::Phrase(Phrase::Emphasis, _)
+        ));
+        assert!(matches!(events[3], SecondPassEvent::Text(8, "and em")));
+        assert!(matches!(
+            events[4],
+            SecondPassEvent::PhraseEnd(Phrase::Emphasis)
+        ));
+        assert!(matches!(
+            events[5],
+            SecondPassEvent::PhraseEnd(Phrase::Bold)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn bracketed_phrase_inside_word() -> Result<()> {
+        let events = events("E=mc[^2^]");
+        assert!(matches!(events[0], SecondPassEvent::Text(0, "E=mc")));
+        assert!(matches!(
+            events[1],
+            SecondPassEvent::Phrase(Phrase::Superscript, _)
+        ));
+        assert!(matches!(events[2], SecondPassEvent::Text(6, "2")));
+        assert_eq!(4, events.len());
+        Ok(())
+    }
+
+    #[test]
+    fn modifiers_inside_words_are_text() -> Result<()> {
+        let events = events("snake_case_name and 2*3*4");
+        assert_eq!(1, events.len());
+        Ok(())
+    }
+
+    #[test]
+    fn code_is_literal() -> Result<()> {
+        let events = events("@*x* <y>@");
+        assert!(matches!(
+            events[0],
+            SecondPassEvent::Phrase(Phrase::Code, _)
+        ));
+        assert!(matches!(events[1], SecondPassEvent::Literal("*x* <y>")));
+        assert!(matches!(
+            events[2],
+            SecondPassEvent::PhraseEnd(Phrase::Code)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn inline_html() -> Result<()> {
+        let events = events("a <b class=\"c\">*d*</b><!-- e --><code><f></code>");
+        assert!(matches!(events[0], SecondPassEvent::Text(0, "a ")));
+        assert!(matches!(
+            events[1],
+            SecondPassEvent::Html("<b class=\"c\">")
+        ));
+        assert!(matches!(
+            events[2],
+            SecondPassEvent::Phrase(Phrase::Strong, _)
+        ));
+        assert!(matches!(events[5], SecondPassEvent::Html("</b>")));
+        assert!(matches!(events[6], SecondPassEvent::Html("<!-- e -->")));
+        assert!(matches!(events[7], SecondPassEvent::Html("<code>")));
+        assert!(matches!(events[8], SecondPassEvent::Literal("<f>")));
+        assert!(matches!(events[9], SecondPassEvent::Html("</code>")));
+        assert_eq!(10, events.len());
+        Ok(())
+    }
+
+    #[test]
+    fn phrase_attributes() -> Result<()> {
+        let events = events("%(note)aside%");
+        match &events[0] {
+            SecondPassEvent::Phrase(Phrase::Span, attributes) => {
+                assert_eq!(Some("note".to_string()), attributes.class)
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+        assert!(matches!(events[1], SecondPassEvent::Text(7, "aside")));
+        Ok(())
+    }
+
+    #[test]
+    fn link_with_phrases() -> Result<()> {
+        let events = events("*\"_go_\":http://example.com*");
+        assert!(matches!(
+            events[0],
+            SecondPassEvent::Phrase(Phrase::Strong, _)
+        ));
+        assert!(matches!(
+            events[1],
+            SecondPassEvent::Link("http://example.com", None, _)
+        ));
+        assert!(matches!(
+            events[2],
+            SecondPassEvent::Phrase(Phrase::Emphasis, _)
+        ));
+        assert!(matches!(events[3], SecondPassEvent::Text(3, "go")));
+        assert!(matches!(events[5], SecondPassEvent::LinkEnd));
+        assert!(matches!(
+            events[6],
+            SecondPassEvent::PhraseEnd(Phrase::Strong)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn self_link() -> Result<()> {
+        let events = events("\"$\":https://example.com");
+        assert!(matches!(
+            events[1],
+            SecondPassEvent::Text(12, "example.com")
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use super::attributes::{parse_attributes, parse_cell_attributes, Attributes};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TableGroup {
+    Head,
+    Body,
+    Foot,
+}
+
+impl TableGroup {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            TableGroup::Head => "thead",
+            TableGroup::Body => "tbody",
+            TableGroup::Foot => "tfoot",
+        }
+    }
+}
+
+/// A `table(class). summary` line. The summary range is a byte offset
+/// into the line.
+#[derive(Debug, Eq, PartialEq)]
+pub struct TableSignature {
+    pub attributes: Attributes,
+    pub summary: Option<(usize, usize)>,
+}
+
+pub fn table_signature(line: &str) -> Option<TableSignature> {
+    let rest = line.strip_prefix("table")?;
+    let (attributes, length) = parse_attributes(rest);
+    let position = 5 + length;
+    if line.as_bytes().get(position) != Some(&b'.') {
+        return None;
+    }
+
+    let summary = &line[position + 1..];
+    if !summary.is_empty() && !summary.starts_with(' ') {
+        return None;
+    }
+    let trimmed = summary.trim();
+    let summary = if trimmed.is_empty() {
+        None
+    } else {
+        let start = position + 1 + (summary.len() - summary.trim_start().len());
+        Some((start, start + trimmed.len()))
+    };
+
+    Some(TableSignature {
+        attributes,
+        summary,
+    })
+}
+
+/// Reads a `|=. caption` line, returning its attributes and the offset
+/// at which the caption starts
+pub fn caption_line(line: &str) -> Option<(Attributes, usize)> {
+    let rest = line.strip_prefix("|=")?;
+    let (attributes, length) = parse_attributes(rest);
+    let position = 2 + length;
+    if !line[position..].starts_with(". ") {
+        return None;
+    }
+    Some((attributes, position + 2))
+}
+
+/// Reads a line made of only a `|^.`, `|-.` or `|~.` group marker
+pub fn group_line(line: &str) -> Option<(TableGroup, Attributes)> {
+    let rest = line.strip_prefix('|')?;
+    let group = match rest.as_bytes().first()? {
+        b'^' => TableGroup::Head,
+        b'-' => TableGroup::Body,
+        b'~' => TableGroup::Foot,
+        _ => return None,
+    };
+    let (attributes, length) = parse_attributes(&rest[1..]);
+    if rest[1 + length..].trim_end() != "." {
+        return None;
+    }
+    Some((group, attributes))
+}
+
+/// Reads the optional `(class). ` row attributes, returning them and the
+/// offset of the first `|`
+pub fn row_start(line: &str) -> Option<(Attributes, usize)> {
+    if line.starts_with('|') {
+        return Some((Attributes::default(), 0));
+    }
+    let (attributes, length) = parse_cell_attributes(line);
+    if length == 0 || !line[length..].starts_with(". ") {
+        return None;
+    }
+    let offset = length + 2;
+    line[offset..]
+        .starts_with('|')
+        .then_some((attributes, offset))
+}
+
+/// Whether a row has been closed by its final `|`, rather than
+/// continuing onto the next line
+pub fn row_is_complete(row: &str) -> bool {
+    row.trim_end().ends_with('|')
+}
+
+/// A cell between two `|`. Ranges are byte offsets into the row.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Cell {
+    pub header: bool,
+    pub attributes: Attributes,
+    pub content: (usize, usize),
+}
+
+/// Splits a row, starting at its first `|`, into cells
+pub fn cells(row: &str, offset: usize) -> Vec<Cell> {
+    let mut cells = vec![];
+    let mut start = offset + 1;
+    while let Some(found) = row[start..].find('|') {
+        let end = start + found;
+        cells.push(cell(row, start, end));
+        start = end + 1;
+    }
+    // Text after the last `|` only counts as a cell when the row was
+    // never closed
+    if !row[start..].trim().is_empty() {
+        cells.push(cell(row, start, row.len()));
+    }
+    cells
+}
+
+fn cell(row: &str, start: usize, end: usize) -> Cell {
+    let text = &row[start..end];
+    let header = text.starts_with('_');
+    let modifiers = if header { &text[1..] } else { text };
+    let (attributes, length) = parse_cell_attributes(modifiers);
+    let after = usize::from(header) + length;
+
+    if text[after..].starts_with(". ") || (after > 0 && text[after..].trim_end() == ".") {
+        let content_start = start + after + 1;
+        let content_start = if row[content_start..end].starts_with(' ') {
+            content_start + 1
+        } else {
+            content_start
+        };
+        return Cell {
+            header,
+            attributes,
+            content: (content_start, end),
+        };
+    }
+
+    Cell {
+        header: false,
+        attributes: Attributes::default(),
+        content: (start, end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn signature_with_summary() -> Result<()> {
+        let signature = table_signature("table(grid). Prices").unwrap();
+        assert_eq!(Some("grid".to_string()), signature.attributes.class);
+        assert_eq!(Some((13, 19)), signature.summary);
+        assert!(table_signature("table(grid).").unwrap().summary.is_none());
+        assert!(table_signature("tables are nice").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn groups_and_captions() -> Result<()> {
+        assert_eq!(
+            Some((TableGroup::Head, Attributes::default())),
+            group_line("|^.")
+        );
+        assert_eq!(TableGroup::Foot, group_line("|~(x).  ").unwrap().0);
+        assert!(group_line("|^. cell|").is_none());
+        assert_eq!(4, caption_line("|=. Caption").unwrap().1);
+        Ok(())
+    }
+
+    #[test]
+    fn row_cells() -> Result<()> {
+        let row = "(odd). |_. a|\\2>. b| c |";
+        let (attributes, offset) = row_start(row).unwrap();
+        assert_eq!(Some("odd".to_string()), attributes.class);
+        assert_eq!(7, offset);
+
+        let cells = cells(row, offset);
+        assert_eq!(3, cells.len());
+        assert!(cells[0].header);
+        assert_eq!("a", &row[cells[0].content.0..cells[0].content.1]);
+        assert_eq!(Some(2), cells[1].attributes.colspan);
+        assert_eq!("b", &row[cells[1].content.0..cells[1].content.1]);
+        assert!(!cells[2].header);
+        assert_eq!(" c ", &row[cells[2].content.0..cells[2].content.1]);
+        Ok(())
+    }
+
+    #[test]
+    fn not_rows() -> Result<()> {
+        assert!(row_start("plain").is_none());
+        assert!(row_start("(aside) |pipe").is_none());
+        Ok(())
+    }
+}
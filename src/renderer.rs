@@ -1,19 +1,1142 @@
-use crate::pulp::Event;
+use crate::glyphs::glyphs;
+use crate::image_size::image_size;
+use crate::options::{Doctype, Symbol};
+use crate::pulp::{scheme, Attributes, Document, Event, NoteBacklinks};
 use crate::Error;
 use crate::Options;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 
-pub fn render<'a, I>(iterator: &mut I, _options: &Options) -> Result<String, Error>
+pub fn render<'a, I>(
+    iterator: &mut I,
+    options: &Options,
+    document: &Document<'a>,
+) -> Result<String, Error>
 where
     I: Iterator<Item = Event<'a>>,
 {
     let mut buffer = String::new();
-    for event in iterator {
+    // Only the first reference to a footnote is the one it links back to
+    let mut referenced = HashSet::new();
+    let mut notes = Notes::default();
+    // The output set aside while a note definition renders into its own
+    // buffer, or while the lines of a `notelist.` are thrown away
+    let mut set_aside = None;
+    // What closes the acronym being written, since its template wraps the
+    // acronym's content
+    let mut acronym_end = String::new();
+    let doctype = options.document_type;
+    let align_classes = options.align_classes_enabled();
+    for mut event in iterator {
+        if align_classes {
+            if let Some(attributes) = event.attributes_mut() {
+                attributes.align_as_class();
+            }
+        }
         match event {
-            Event::Paragraph => buffer.push_str("<p>"),
+            Event::Paragraph(attributes) => push_tag(&mut buffer, "p", &attributes, doctype),
             Event::ParagraphEnd => buffer.push_str("</p>"),
-            Event::Text(_, _, text) => buffer.push_str(text),
-            Event::LineBreak(_, _) => buffer.push_str("<br>\n"),
+            // Unwrapped paragraphs are still kept apart from what came before
+            Event::BareParagraph if !buffer.is_empty() => buffer.push_str("\n\n"),
+            Event::BareParagraph | Event::BareParagraphEnd => {}
+            Event::Heading(level, attributes) => {
+                push_tag(&mut buffer, &format!("h{}", level), &attributes, doctype)
+            }
+            Event::HeadingEnd(level) => {
+                buffer.push_str("</h");
+                buffer.push((b'0' + level) as char);
+                buffer.push('>');
+            }
+            Event::BlockQuote(cite, attributes) => {
+                buffer.push_str("<blockquote");
+                if let Some(cite) = cite {
+                    push_attribute(&mut buffer, "cite", cite);
+                }
+                push_attributes(&mut buffer, &attributes, doctype);
+                buffer.push('>');
+            }
+            Event::BlockQuoteEnd => buffer.push_str("</blockquote>"),
+            Event::BlockCode(attributes) => {
+                push_tag(&mut buffer, "pre", &attributes, doctype);
+                push_tag(&mut buffer, "code", &attributes.without_id(), doctype);
+            }
+            Event::BlockCodeEnd => buffer.push_str("</code></pre>"),
+            Event::Preformatted(attributes) => push_tag(&mut buffer, "pre", &attributes, doctype),
+            Event::PreformattedEnd => buffer.push_str("</pre>"),
+            Event::NoTextile | Event::NoTextileEnd => {}
+            Event::HtmlBlock | Event::HtmlBlockEnd => {}
+            Event::Footnote(number, backlink, mut attributes) => {
+                let id = format!("fn{}-{}", document.id_prefix, number);
+                attributes
+                    .class
+                    .get_or_insert_with(|| "footnote".to_string());
+                // An id given by the author goes on the paragraph, pushing
+                // the generated one onto the number
+                let number_id = match attributes.id {
+                    Some(_) => Some(id),
+                    None => {
+                        attributes.id = Some(id);
+                        None
+                    }
+                };
+                push_tag(&mut buffer, "p", &attributes, doctype);
+                let mut atts = String::new();
+                if let Some(id) = number_id {
+                    push_attribute(&mut atts, "id", &id);
+                }
+                let marker = if backlink {
+                    let href = format!("#fnrev{}-{}", document.id_prefix, number);
+                    link_to(&href, number)
+                } else {
+                    number.to_string()
+                };
+                push_pattern(
+                    &mut buffer,
+                    options,
+                    Symbol::FootnotePattern,
+                    &[("atts", &atts), ("marker", &marker)],
+                );
+                buffer.push(' ');
+            }
+            Event::FootnoteEnd => buffer.push_str("</p>"),
+            Event::List(kind, attributes, start) => {
+                buffer.push('<');
+                buffer.push_str(kind.tag());
+                push_attributes(&mut buffer, &attributes, doctype);
+                if let Some(start) = start {
+                    push_attribute(&mut buffer, "start", &start.to_string());
+                }
+                buffer.push('>');
+            }
+            Event::ListEnd(kind) => {
+                buffer.push_str("</");
+                buffer.push_str(kind.tag());
+                buffer.push('>');
+            }
+            Event::ListItem(attributes) => push_tag(&mut buffer, "li", &attributes, doctype),
+            Event::ListItemEnd => buffer.push_str("</li>"),
+            Event::DefinitionList(attributes) => push_tag(&mut buffer, "dl", &attributes, doctype),
+            Event::DefinitionListEnd => buffer.push_str("</dl>"),
+            Event::Term(attributes) => push_tag(&mut buffer, "dt", &attributes, doctype),
+            Event::TermEnd => buffer.push_str("</dt>"),
+            Event::Definition(attributes) => push_tag(&mut buffer, "dd", &attributes, doctype),
+            Event::DefinitionEnd => buffer.push_str("</dd>"),
+            Event::Table(attributes, summary) => {
+                buffer.push_str("<table");
+                push_attributes(&mut buffer, &attributes, doctype);
+                if let Some(summary) = summary {
+                    push_attribute(&mut buffer, "summary", summary);
+                }
+                buffer.push('>');
+            }
+            Event::TableEnd => buffer.push_str("</table>"),
+            Event::TableCaption(attributes) => {
+                push_tag(&mut buffer, "caption", &attributes, doctype)
+            }
+            Event::TableCaptionEnd => buffer.push_str("</caption>"),
+            Event::TableGroup(group, attributes) => {
+                push_tag(&mut buffer, group.tag(), &attributes, doctype)
+            }
+            Event::TableGroupEnd(group) => {
+                buffer.push_str("</");
+                buffer.push_str(group.tag());
+                buffer.push('>');
+            }
+            Event::TableRow(attributes) => push_tag(&mut buffer, "tr", &attributes, doctype),
+            Event::TableRowEnd => buffer.push_str("</tr>"),
+            Event::TableCell(header, attributes) => push_tag(
+                &mut buffer,
+                if header { "th" } else { "td" },
+                &attributes,
+                doctype,
+            ),
+            Event::TableCellEnd(header) => buffer.push_str(if header { "</th>" } else { "</td>" }),
+            Event::Phrase(phrase, attributes) => {
+                push_tag(&mut buffer, phrase.tag(), &attributes, doctype)
+            }
+            Event::PhraseEnd(phrase) => {
+                buffer.push_str("</");
+                buffer.push_str(phrase.tag());
+                buffer.push('>');
+            }
+            Event::Link(href, title, attributes) => {
+                let href = prefixed(document.link_aliases.resolve(href), &options.link_prefix);
+                let mut extra = vec![("href", encode_url(&href))];
+                if let Some(title) = title {
+                    extra.push(("title", title.to_string()));
+                }
+                if let Some(rel) = link_rel(options, &href) {
+                    extra.push(("rel", rel));
+                }
+                push_tag_with(&mut buffer, "a", &attributes, extra, doctype);
+            }
+            Event::LinkEnd => buffer.push_str("</a>"),
+            Event::Image(src, alt, align, mut attributes) => {
+                let mut extra = vec![
+                    ("alt", alt.unwrap_or_default().to_string()),
+                    ("src", encode_url(&prefixed(src, &options.image_prefix))),
+                ];
+                if let Some(alt) = alt {
+                    extra.push(("title", alt.to_string()));
+                }
+                if let Some((width, height)) = dimensions(options, src) {
+                    extra.push(("height", height.to_string()));
+                    extra.push(("width", width.to_string()));
+                }
+                if let Some(align) = align {
+                    if align_classes {
+                        attributes.add_class(&format!("align-{}", align.as_str()));
+                    } else {
+                        extra.push(("align", align.as_str().to_string()));
+                    }
+                }
+                push_start_tag(&mut buffer, "img", &attributes, extra, doctype);
+                buffer.push_str(match doctype {
+                    Doctype::Html5 => ">",
+                    Doctype::Xhtml => " />",
+                });
+            }
+            Event::FootnoteReference(number, link) => {
+                let mut atts = String::new();
+                push_attribute(&mut atts, "class", "footnote");
+                if referenced.insert(number) {
+                    let id = format!("fnrev{}-{}", document.id_prefix, number);
+                    push_attribute(&mut atts, "id", &id);
+                }
+                let marker = if link {
+                    let href = format!("#fn{}-{}", document.id_prefix, number);
+                    link_to(&href, number)
+                } else {
+                    number.to_string()
+                };
+                push_pattern(
+                    &mut buffer,
+                    options,
+                    Symbol::FootnoteReferencePattern,
+                    &[("atts", &atts), ("marker", &marker)],
+                );
+            }
+            Event::NoteReference(label, link, attributes) => {
+                let (number, reference) = notes.reference(label);
+                let mut atts = String::new();
+                push_attributes(&mut atts, &attributes, doctype);
+                let mut marker = String::from("<span");
+                let id = format!("noteref{}-{}-{}", document.id_prefix, label, reference);
+                push_attribute(&mut marker, "id", &id);
+                marker.push('>');
+                marker.push_str(&number.to_string());
+                marker.push_str("</span>");
+                if link {
+                    let href = format!("#note{}-{}", document.id_prefix, label);
+                    marker = link_to(&href, &marker);
+                }
+                push_pattern(
+                    &mut buffer,
+                    options,
+                    Symbol::NoteReferencePattern,
+                    &[("atts", &atts), ("marker", &marker)],
+                );
+            }
+            Event::Acronym(title) => {
+                let template = match options.symbols.get(&Symbol::Acronym) {
+                    Some(template) => template.clone(),
+                    None => {
+                        let tag = match doctype {
+                            Doctype::Html5 => "abbr",
+                            Doctype::Xhtml => "acronym",
+                        };
+                        format!("<{0} title=\"{{title}}\">{{content}}</{0}>", tag)
+                    }
+                };
+                let (start, end) = template.split_once("{content}").unwrap_or((&template, ""));
+                let mut title_escaped = String::new();
+                push_escaped(&mut title_escaped, title);
+                let values = [("title", title_escaped.as_str())];
+                push_template(&mut buffer, start, &values);
+                acronym_end.clear();
+                push_template(&mut acronym_end, end, &values);
+            }
+            Event::AcronymEnd => buffer.push_str(&acronym_end),
+            // Without a template, capitals are written as they are
+            Event::Caps(content) => match options.symbols.get(&Symbol::Caps) {
+                Some(template) => push_template(&mut buffer, template, &[("content", content)]),
+                None => buffer.push_str(content),
+            },
+            Event::NoteDefinition(label, backlinks, attributes) => {
+                notes.defined.push((label, backlinks, attributes));
+                set_aside = Some(mem::take(&mut buffer));
+            }
+            Event::NoteDefinitionEnd => {
+                let content = mem::replace(&mut buffer, set_aside.take().unwrap_or_default());
+                if let Some((label, _, _)) = notes.defined.last() {
+                    notes.contents.entry(label).or_insert(content);
+                }
+            }
+            Event::NoteList(attributes, start, backlinks, unreferenced) => {
+                notes.lists.push(NoteList {
+                    offset: buffer.len(),
+                    attributes,
+                    start: start.unwrap_or("a"),
+                    backlinks: backlinks.unwrap_or(NoteBacklinks::All),
+                    unreferenced,
+                });
+                set_aside = Some(mem::take(&mut buffer));
+            }
+            Event::NoteListEnd => buffer = set_aside.take().unwrap_or_default(),
+            Event::Text(_, text) => {
+                // Authors may write entities themselves, but restricted
+                // mode escapes those too
+                let escaped = escape_markup(text, !options.restricted_mode);
+                let text = glyphs(&escaped, preceding(&buffer), &options.symbols);
+                buffer.push_str(&text);
+            }
+            Event::Literal(text) => push_escaped(&mut buffer, text),
+            Event::Html(html) if options.restricted_mode => push_escaped(&mut buffer, html),
+            Event::Html(html) => buffer.push_str(html),
+            Event::LineBreak if options.line_wrap => buffer.push_str(match doctype {
+                Doctype::Html5 => "<br>\n",
+                Doctype::Xhtml => "<br />\n",
+            }),
+            Event::LineBreak => buffer.push('\n'),
         }
     }
+    // Note lists can only be filled in once every note and reference has
+    // been seen
+    for list in notes.lists.iter().rev() {
+        let html = notes.list(list, &document.id_prefix, options.document_type);
+        buffer.insert_str(list.offset, &html);
+    }
     Ok(buffer)
 }
+
+/// The endnotes of the document, collected while rendering
+#[derive(Default)]
+struct Notes<'a> {
+    /// Labels in the order they were first referenced, which is how the
+    /// notes are numbered
+    referenced: Vec<&'a str>,
+    /// The number of references to each label
+    references: HashMap<&'a str, usize>,
+    /// Every definition in document order, including repeated labels
+    defined: Vec<(&'a str, Option<NoteBacklinks>, Attributes)>,
+    /// The rendered content of each label's first definition
+    contents: HashMap<&'a str, String>,
+    lists: Vec<NoteList<'a>>,
+}
+
+/// A `notelist.` waiting to be filled in at `offset` of the output
+struct NoteList<'a> {
+    offset: usize,
+    attributes: Attributes,
+    start: &'a str,
+    backlinks: NoteBacklinks,
+    unreferenced: bool,
+}
+
+impl<'a> Notes<'a> {
+    /// Counts a reference, returning the number of its note and which
+    /// reference to the note it is
+    fn reference(&mut self, label: &'a str) -> (usize, usize) {
+        let count = self.references.entry(label).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            self.referenced.push(label);
+        }
+        let number = self
+            .referenced
+            .iter()
+            .position(|other| *other == label)
+            .unwrap_or(0)
+            + 1;
+        (number, *count)
+    }
+
+    fn definition(&self, label: &str) -> Option<&(&'a str, Option<NoteBacklinks>, Attributes)> {
+        self.defined
+            .iter()
+            .find(|(defined, _, _)| *defined == label)
+    }
+
+    fn list(&self, list: &NoteList<'a>, id_prefix: &str, doctype: Doctype) -> String {
+        let mut items = String::new();
+        for (index, label) in self.referenced.iter().enumerate() {
+            let definition = self.definition(label);
+            let backlinks = definition
+                .and_then(|(_, backlinks, _)| *backlinks)
+                .unwrap_or(list.backlinks);
+            match definition {
+                Some((_, _, attributes)) => {
+                    push_tag(&mut items, "li", attributes, doctype);
+                    self.push_backlinks(&mut items, label, backlinks, list.start, id_prefix);
+                    items.push_str("<span");
+                    push_attribute(&mut items, "id", &format!("note{}-{}", id_prefix, label));
+                    items.push_str("> </span>");
+                    items.push_str(&self.contents[label]);
+                }
+                None => {
+                    items.push_str("<li>");
+                    self.push_backlinks(&mut items, label, backlinks, list.start, id_prefix);
+                    items.push_str(&format!(" Undefined Note [#{}].", index + 1));
+                }
+            }
+            items.push_str("</li>");
+        }
+        if list.unreferenced {
+            let mut listed = HashSet::new();
+            for (label, _, attributes) in &self.defined {
+                if self.references.contains_key(label) || !listed.insert(label) {
+                    continue;
+                }
+                push_tag(&mut items, "li", attributes, doctype);
+                items.push_str(&self.contents[label]);
+                items.push_str("</li>");
+            }
+        }
+
+        if items.is_empty() {
+            return items;
+        }
+        let mut html = String::new();
+        push_tag(&mut html, "ol", &list.attributes, doctype);
+        html.push_str(&items);
+        html.push_str("</ol>");
+        html
+    }
+
+    fn push_backlinks(
+        &self,
+        buffer: &mut String,
+        label: &str,
+        backlinks: NoteBacklinks,
+        start: &str,
+        id_prefix: &str,
+    ) {
+        let count = match backlinks {
+            NoteBacklinks::None => return,
+            NoteBacklinks::First => 1,
+            NoteBacklinks::All => self.references.get(label).copied().unwrap_or(0),
+        };
+        let mut marker = start.to_string();
+        for reference in 1..=count {
+            if reference > 1 {
+                buffer.push(' ');
+                increment(&mut marker);
+            }
+            buffer.push_str("<sup><a");
+            let href = format!("#noteref{}-{}-{}", id_prefix, label, reference);
+            push_attribute(buffer, "href", &href);
+            buffer.push('>');
+            buffer.push_str(&marker);
+            buffer.push_str("</a></sup>");
+        }
+    }
+}
+
+/// Steps a backlink marker on the way PHP increments strings, so `a`
+/// becomes `b`, `z` becomes `aa` and `9` becomes `10`. Symbols such as
+/// `†` stay the same.
+fn increment(marker: &mut String) {
+    if !marker.chars().all(|char| char.is_ascii_alphanumeric()) {
+        return;
+    }
+    let mut bytes = mem::take(marker).into_bytes();
+    for index in (0..bytes.len()).rev() {
+        let (wrapped, carried) = match bytes[index] {
+            b'z' => (b'a', b'a'),
+            b'Z' => (b'A', b'A'),
+            b'9' => (b'0', b'1'),
+            byte => {
+                bytes[index] = byte + 1;
+                break;
+            }
+        };
+        bytes[index] = wrapped;
+        if index == 0 {
+            bytes.insert(0, carried);
+        }
+    }
+    *marker = String::from_utf8(bytes).unwrap_or_default();
+}
+
+/// Writes the markup of one of the templates in the symbol table
+fn push_pattern(buffer: &mut String, options: &Options, symbol: Symbol, values: &[(&str, &str)]) {
+    if let Some(template) = options.symbols.get(&symbol) {
+        push_template(buffer, template, values);
+    }
+}
+
+/// Writes a template, filling in its `{placeholders}`. Unknown ones are
+/// written as they are.
+fn push_template(buffer: &mut String, mut template: &str, values: &[(&str, &str)]) {
+    while let Some(open) = template.find('{') {
+        buffer.push_str(&template[..open]);
+        template = &template[open..];
+        let value = template.find('}').and_then(|close| {
+            let name = &template[1..close];
+            let (_, value) = values
+                .iter()
+                .find(|(placeholder, _)| *placeholder == name)?;
+            Some((value, close))
+        });
+        match value {
+            Some((value, close)) => {
+                buffer.push_str(value);
+                template = &template[close + 1..];
+            }
+            None => {
+                buffer.push('{');
+                template = &template[1..];
+            }
+        }
+    }
+    buffer.push_str(template);
+}
+
+/// An `<a>` around already rendered content
+fn link_to(href: &str, content: &str) -> String {
+    let mut link = String::from("<a");
+    push_attribute(&mut link, "href", href);
+    link.push('>');
+    link.push_str(content);
+    link.push_str("</a>");
+    link
+}
+
+/// The character written before the text about to be, as far as quotes
+/// are concerned. An element that ends counts as the text inside it, while
+/// the start of one counts as the start of the text.
+fn preceding(buffer: &str) -> Option<char> {
+    if !buffer.ends_with('>') {
+        return buffer.chars().next_back();
+    }
+    let open = buffer.rfind('<')?;
+    let tag = &buffer[open..];
+    if tag.starts_with("</") {
+        preceding(&buffer[..open])
+    } else {
+        tag.ends_with("/>").then_some('>')
+    }
+}
+
+fn dimensions(options: &Options, src: &str) -> Option<(u32, u32)> {
+    if options.dimensionless_images {
+        return None;
+    }
+    image_size(options.document_root_directory.as_ref()?, src)
+}
+
+fn push_tag(buffer: &mut String, name: &str, attributes: &Attributes, doctype: Doctype) {
+    push_tag_with(buffer, name, attributes, vec![], doctype);
+}
+
+fn push_tag_with(
+    buffer: &mut String,
+    name: &str,
+    attributes: &Attributes,
+    extra: Vec<(&'static str, String)>,
+    doctype: Doctype,
+) {
+    push_start_tag(buffer, name, attributes, extra, doctype);
+    buffer.push('>');
+}
+
+/// Writes a tag up to its closing `>` or `/>`, with the attributes,
+/// including the `extra` ones, sorted by name like php-textile's tags
+fn push_start_tag(
+    buffer: &mut String,
+    name: &str,
+    attributes: &Attributes,
+    mut extra: Vec<(&'static str, String)>,
+    doctype: Doctype,
+) {
+    let mut list = attribute_list(attributes, doctype);
+    list.append(&mut extra);
+    list.sort_by_key(|(name, _)| *name);
+
+    buffer.push('<');
+    buffer.push_str(name);
+    for (name, value) in list {
+        push_attribute(buffer, name, &value);
+    }
+}
+
+fn push_attributes(buffer: &mut String, attributes: &Attributes, doctype: Doctype) {
+    for (name, value) in attribute_list(attributes, doctype) {
+        push_attribute(buffer, name, &value);
+    }
+}
+
+/// Attributes in the alphabetical order php-textile uses
+fn attribute_list(attributes: &Attributes, doctype: Doctype) -> Vec<(&'static str, String)> {
+    let mut list = vec![];
+    if let Some(class) = &attributes.class {
+        list.push(("class", class.clone()));
+    }
+    if let Some(colspan) = attributes.colspan {
+        list.push(("colspan", colspan.to_string()));
+    }
+    if let Some(id) = &attributes.id {
+        list.push(("id", id.clone()));
+    }
+    if let Some(lang) = &attributes.lang {
+        list.push(("lang", lang.clone()));
+        // XHTML served as HTML needs both, as its compatibility guidelines
+        // recommend
+        if doctype == Doctype::Xhtml {
+            list.push(("xml:lang", lang.clone()));
+        }
+    }
+    if let Some(rowspan) = attributes.rowspan {
+        list.push(("rowspan", rowspan.to_string()));
+    }
+    let declarations = attributes.style_declarations();
+    if !declarations.is_empty() {
+        let mut style = String::new();
+        for declaration in declarations {
+            style.push_str(&declaration);
+            style.push(';');
+        }
+        list.push(("style", style));
+    }
+    list
+}
+
+fn push_attribute(buffer: &mut String, name: &str, value: &str) {
+    buffer.push(' ');
+    buffer.push_str(name);
+    buffer.push_str("=\"");
+    push_escaped(buffer, value);
+    buffer.push('"');
+}
+
+/// The `rel` of a link to `href`, which always includes `nofollow` in
+/// restricted mode
+fn link_rel(options: &Options, href: &str) -> Option<String> {
+    let rel = options.link_rel.as_ref().and_then(|rel| rel.for_href(href));
+    if !options.restricted_mode {
+        return rel;
+    }
+    match rel {
+        Some(rel) if rel.split_whitespace().any(|value| value == "nofollow") => Some(rel),
+        Some(rel) => Some(format!("{} nofollow", rel)),
+        None => Some("nofollow".to_string()),
+    }
+}
+
+/// Puts `prefix` in front of a relative URL, one starting with a word
+/// character and without a scheme, as it is. Absolute paths, `../`,
+/// fragments and URLs with a scheme or a host are left alone.
+fn prefixed(url: &str, prefix: &Option<String>) -> String {
+    let relative = url.starts_with(|char: char| char.is_alphanumeric() || char == '_');
+    match prefix {
+        Some(prefix) if relative && scheme(url).is_none() => format!("{}{}", prefix, url),
+        _ => url.to_string(),
+    }
+}
+
+/// Percent-encodes the bytes a URL can't carry as they are, leaving
+/// markup escaping to `push_attribute`
+fn encode_url(url: &str) -> String {
+    let mut encoded = String::with_capacity(url.len());
+    for char in url.chars() {
+        if char.is_ascii() && !char.is_ascii_whitespace() && !char.is_ascii_control() {
+            encoded.push(char);
+            continue;
+        }
+        let mut bytes = [0; 4];
+        for byte in char.encode_utf8(&mut bytes).bytes() {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Escapes the characters that would otherwise be read as markup
+fn push_escaped(buffer: &mut String, text: &str) {
+    for char in text.chars() {
+        match char {
+            '&' => buffer.push_str("&amp;"),
+            '<' => buffer.push_str("&lt;"),
+            '>' => buffer.push_str("&gt;"),
+            '"' => buffer.push_str("&quot;"),
+            '\'' => buffer.push_str("&#39;"),
+            _ => buffer.push(char),
+        }
+    }
+}
+
+/// Escapes the characters that start markup, leaving quotes for glyphs
+/// to turn into curly ones. With `keep_entities`, an `&` that starts an
+/// entity reference such as `&copy;` or `&#169;` is left as it is.
+fn escape_markup(text: &str, keep_entities: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (position, char) in text.char_indices() {
+        match char {
+            '&' if keep_entities && is_entity(&text[position..]) => escaped.push(char),
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+/// Whether `text` starts with an entity reference, `&name;` or `&#123;`
+fn is_entity(text: &str) -> bool {
+    let rest = text.strip_prefix('&').unwrap_or(text);
+    let rest = rest.strip_prefix('#').unwrap_or(rest);
+    let length = rest
+        .find(|char: char| !char.is_ascii_alphanumeric())
+        .unwrap_or(rest.len());
+    length > 0 && rest[length..].starts_with(';')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pulp::Parser;
+    use crate::LinkRel;
+    use anyhow::Result;
+
+    /// Renders `textile` through the whole pipeline, for checking the
+    /// markup the events turn into
+    fn to_html(textile: &str, options: Options) -> Result<String> {
+        let mut parser = Parser::new_with_options(textile, &options);
+        let document = parser.document().clone();
+        Ok(render(&mut parser, &options, &document)?)
+    }
+
+    #[test]
+    fn backlink_markers() -> Result<()> {
+        let steps = |start: &str, times: usize| {
+            let mut marker = start.to_string();
+            for _ in 0..times {
+                increment(&mut marker);
+            }
+            marker
+        };
+        assert_eq!("c", steps("a", 2));
+        assert_eq!("aa", steps("z", 1));
+        assert_eq!("Ba", steps("Az", 1));
+        assert_eq!("10", steps("9", 1));
+        assert_eq!("†", steps("†", 3));
+        Ok(())
+    }
+
+    #[test]
+    fn url_prefixes() -> Result<()> {
+        let prefix = Some("https://cdn.example/assets/".to_string());
+        assert_eq!(
+            "https://cdn.example/assets/a/b.png",
+            prefixed("a/b.png", &prefix)
+        );
+        assert_eq!("img_x.png", prefixed("x.png", &Some("img_".to_string())));
+        assert_eq!("/c", prefixed("/c", &prefix));
+        assert_eq!("../up", prefixed("../up", &prefix));
+        assert_eq!("http://e.org/d", prefixed("http://e.org/d", &prefix));
+        assert_eq!("mailto:me@e.org", prefixed("mailto:me@e.org", &prefix));
+        assert_eq!("#top", prefixed("#top", &prefix));
+        assert_eq!("//e.org/e", prefixed("//e.org/e", &prefix));
+        assert_eq!("f", prefixed("f", &None));
+        Ok(())
+    }
+
+    #[test]
+    fn text_escaping() -> Result<()> {
+        assert_eq!("<p>a &amp; b</p>", to_html("a & b", Options::default())?);
+        assert_eq!("<p>AT&amp;T</p>", to_html("AT&T", Options::default())?);
+        assert_eq!(
+            "<p>&copy; &#169; &amp;c</p>",
+            to_html("&copy; &#169; &c", Options::default())?
+        );
+        assert_eq!(
+            "<p>&#8220;a&#8221; &amp; b &lt; c &gt; d</p>",
+            to_html("\"a\" & b < c > d", Options::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn patterns() -> Result<()> {
+        let mut options = Options::default();
+        let mut buffer = String::new();
+        let values = [("atts", " id=\"x\""), ("marker", "1")];
+        push_pattern(&mut buffer, &options, Symbol::FootnotePattern, &values);
+        assert_eq!("<sup id=\"x\">1</sup>", buffer);
+
+        options.symbols.insert(
+            Symbol::FootnotePattern,
+            "[{marker}]{unknown}{atts".to_string(),
+        );
+        buffer.clear();
+        push_pattern(&mut buffer, &options, Symbol::FootnotePattern, &values);
+        assert_eq!("[1]{unknown}{atts", buffer);
+        Ok(())
+    }
+
+    #[test]
+    fn block_signatures() -> Result<()> {
+        assert_eq!(
+            "<h2>Title</h2><blockquote><p>Quote</p></blockquote><pre><code>a &lt; b</code></pre>",
+            to_html("h2. Title\n\nbq. Quote\n\nbc. a < b", Options::default())?
+        );
+        assert_eq!(
+            "<pre>a &lt; *b*</pre><div>*c*</div>",
+            to_html(
+                "pre. a < *b*\n\nnotextile. <div>*c*</div>",
+                Options::default()
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn extended_blocks() -> Result<()> {
+        assert_eq!(
+            "<pre><code>a\n\nb</code></pre><p>c</p>",
+            to_html("bc.. a\n\nb\n\np. c", Options::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_attributes() -> Result<()> {
+        assert_eq!(
+            "<h1 class=\"title\" id=\"top\" lang=\"en\" style=\"color:red;padding-left:1em;text-align:center;\" xml:lang=\"en\">Hi</h1>",
+            to_html("h1(title#top){color:red}[en](=. Hi", Options::default())?
+        );
+        assert_eq!(
+            "<pre class=\"rust\" id=\"x\"><code class=\"rust\">fn</code></pre>",
+            to_html("bc(rust#x). fn", Options::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn phrase_modifiers() -> Result<()> {
+        assert_eq!(
+            "<p><strong>bold</strong>, <em>em</em> and <code>&lt;b&gt;</code></p>",
+            to_html("*bold*, _em_ and @<b>@", Options::default())?
+        );
+        assert_eq!(
+            "<p>a<span class=\"x\">b</span>c <del>gone</del> <ins>new</ins> <cite>src</cite></p>",
+            to_html("a[%(x)b%]c -gone- +new+ ??src??", Options::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lists() -> Result<()> {
+        assert_eq!(
+            "<ol class=\"steps\"><li>One<ul><li><em>sub</em></li></ul></li><li>Two</li></ol>",
+            to_html("#(steps) One\n#* _sub_\n# Two", Options::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn definition_lists() -> Result<()> {
+        assert_eq!(
+            "<dl><dt>term</dt><dd><em>def</em></dd></dl>",
+            to_html("- term := _def_", Options::default())?
+        );
+        assert_eq!(
+            "<dl><dt>a</dt><dd>b</dd></dl><p>c</p>",
+            to_html("; a\n: b\n\nc", Options::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tables() -> Result<()> {
+        assert_eq!(
+            "<table class=\"t\"><tr class=\"r\"><th>*</th><td colspan=\"2\" style=\"text-align:right;vertical-align:top;\"><strong>b</strong></td></tr></table>",
+            to_html("table(t).\n(r). |_. *|\\2>^. *b*|", Options::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn links() -> Result<()> {
+        assert_eq!(
+            "<p>Go <a class=\"ext\" href=\"http://example.com/?a=1&amp;b=2\" title=\"Example\">here</a>.</p>",
+            to_html(
+                "Go \"(ext)here(Example)\":http://example.com/?a=1&b=2.",
+                Options::default()
+            )?
+        );
+        assert_eq!(
+            "<p>x<a href=\"/y\">y</a>z</p>",
+            to_html("x[\"y\":/y]z", Options::default())?
+        );
+        assert_eq!(
+            "<p><a href=\"/caf%C3%A9\">/café</a></p>",
+            to_html("\"$\":/café", Options::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn link_aliases() -> Result<()> {
+        assert_eq!(
+            "<p>See <a href=\"http://example.com/\">home</a> and <a href=\"http://example.com/\">example.com/</a>.</p>",
+            to_html(
+                "See \"home\":ex and \"$\":ex.\n[ex]http://example.com/\n\n[unused]/nowhere",
+                Options::default()
+            )?
+        );
+        assert_eq!(
+            "<p><a href=\"x\">l</a></p><pre>\n[x]http://a.com/\n</pre>",
+            to_html(
+                "\"l\":x\n\n<pre>\n[x]http://a.com/\n</pre>",
+                Options::default()
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn images() -> Result<()> {
+        assert_eq!(
+            "<p>A <img alt=\"Cat\" class=\"pet\" src=\"/cat%20s.png\" title=\"Cat\" /> and <a href=\"http://example.com/\"><img align=\"right\" alt=\"\" src=\"dog.png\" /></a>!</p>",
+            to_html(
+                "A !(pet)/cat%20s.png(Cat)! and !>dog.png!:http://example.com/!",
+                Options::default()
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn footnotes() -> Result<()> {
+        let textile = "Claim[1] and again[1], quietly[2!].\n\nfn1^. Source\n\nfn2(src#two). Other";
+        let expected = [
+            "<p>Claim<sup class=\"footnote\" id=\"fnrev70f5241bc7162a14-1\"><a href=\"#fn70f5241bc7162a14-1\">1</a></sup>",
+            " and again<sup class=\"footnote\"><a href=\"#fn70f5241bc7162a14-1\">1</a></sup>,",
+            " quietly<sup class=\"footnote\" id=\"fnrev70f5241bc7162a14-2\">2</sup>.</p>",
+            "<p class=\"footnote\" id=\"fn70f5241bc7162a14-1\"><sup><a href=\"#fnrev70f5241bc7162a14-1\">1</a></sup> Source</p>",
+            "<p class=\"src\" id=\"two\"><sup id=\"fn70f5241bc7162a14-2\">2</sup> Other</p>",
+        ]
+        .concat();
+        assert_eq!(expected, to_html(textile, Options::default())?);
+        Ok(())
+    }
+
+    #[test]
+    fn notes() -> Result<()> {
+        let textile = [
+            "notelist(notes)+.",
+            "",
+            "Tea[#tea] and coffee[#coffee], more tea[#tea!].",
+            "",
+            "note#tea. *Hot* water.",
+            "",
+            "note#coffee^. Beans.",
+            "",
+            "note#extra(x). Not referenced.",
+            "",
+            "notelist:1!.",
+        ]
+        .join("\n");
+        let expected = [
+            "<ol class=\"notes\">",
+            "<li><sup><a href=\"#noteref99c208bfca53167d-tea-1\">a</a></sup> <sup><a href=\"#noteref99c208bfca53167d-tea-2\">b</a></sup>",
+            "<span id=\"note99c208bfca53167d-tea\"> </span><strong>Hot</strong> water.</li>",
+            "<li><sup><a href=\"#noteref99c208bfca53167d-coffee-1\">a</a></sup><span id=\"note99c208bfca53167d-coffee\"> </span>Beans.</li>",
+            "<li class=\"x\">Not referenced.</li>",
+            "</ol>",
+            "<p>Tea<sup><a href=\"#note99c208bfca53167d-tea\"><span id=\"noteref99c208bfca53167d-tea-1\">1</span></a></sup>",
+            " and coffee<sup><a href=\"#note99c208bfca53167d-coffee\"><span id=\"noteref99c208bfca53167d-coffee-1\">2</span></a></sup>,",
+            " more tea<sup><span id=\"noteref99c208bfca53167d-tea-2\">1</span></sup>.</p>",
+            "<ol>",
+            "<li><span id=\"note99c208bfca53167d-tea\"> </span><strong>Hot</strong> water.</li>",
+            "<li><sup><a href=\"#noteref99c208bfca53167d-coffee-1\">1</a></sup><span id=\"note99c208bfca53167d-coffee\"> </span>Beans.</li>",
+            "</ol>",
+        ]
+        .concat();
+        assert_eq!(expected, to_html(&textile, Options::default())?);
+        Ok(())
+    }
+
+    #[test]
+    fn glyphs() -> Result<()> {
+        assert_eq!(
+            "<p>&#8220;<strong>Hi</strong>&#8221; &#8212; it&#8217;s <code>x--y</code></p>",
+            to_html("\"*Hi*\" -- it's @x--y@", Options::default())?
+        );
+
+        let mut options = Options::default();
+        options
+            .symbols
+            .insert(Symbol::Emdash, "&mdash;".to_string());
+        options.symbols.remove(&Symbol::Copyright);
+        assert_eq!("<p>a&mdash;b (c)</p>", to_html("a--b (c)", options)?);
+        Ok(())
+    }
+
+    #[test]
+    fn footnote_patterns() -> Result<()> {
+        let mut options = Options::default();
+        options.symbols.insert(
+            Symbol::FootnoteReferencePattern,
+            "<span class=\"ref\">[{marker}]</span>".to_string(),
+        );
+        options.symbols.insert(
+            Symbol::FootnotePattern,
+            "<b{atts}>{marker}.</b>".to_string(),
+        );
+        assert_eq!(
+            "<p>Fact<span class=\"ref\">[1]</span></p><p class=\"footnote\" id=\"x\"><b id=\"fn615225259ad14c11-1\">1.</b> Source</p>",
+            to_html("Fact[1!]\n\nfn1(#x). Source", options)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn acronyms_and_caps() -> Result<()> {
+        assert_eq!(
+            "<p>The <acronym title=\"Always Be Closing\"><span class=\"caps\">ABC</span></acronym> of <span class=\"caps\">NASA</span>&#8217;s <acronym title=\"World Wide Web Consortium\">W3C</acronym></p>",
+            to_html(
+                "The ABC(Always Be Closing) of NASA's W3C(World Wide Web Consortium)",
+                Options::default()
+            )?
+        );
+
+        let mut options = Options::default();
+        options.symbols.remove(&Symbol::Caps);
+        assert_eq!("<p>No CAPS here</p>", to_html("No CAPS here", options)?);
+        Ok(())
+    }
+
+    #[test]
+    fn html() -> Result<()> {
+        assert_eq!(
+            "<div class=\"note\"><h2>Title</h2><p>Some <em>em</em> and <span title=\"x\">&#8220;span&#8221;</span></p></div>",
+            to_html(
+                "<div class=\"note\">\n\nh2. Title\n\nSome _em_ and <span title=\"x\">\"span\"</span>\n\n</div>",
+                Options::default()
+            )?
+        );
+        assert_eq!(
+            "<pre>a &lt; &quot;b&quot;</pre><!-- kept --><p>x <code>&lt;y&gt;</code></p>",
+            to_html(
+                "<pre>a < \"b\"</pre>\n\n<!-- kept -->\n\nx <code><y></code>",
+                Options::default()
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lite_mode() -> Result<()> {
+        let options = Options {
+            lite_mode: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p>h2. <em>Bio</em></p><blockquote><p>It&#8217;s me</p></blockquote><p># one<br />\nbc. two</p>",
+            to_html("h2. _Bio_\n\nbq. It's me\n\n# one\nbc. two", options)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn line_wrap_and_block_tags() -> Result<()> {
+        let options = Options {
+            line_wrap: false,
+            ..Default::default()
+        };
+        assert_eq!("<p>one\ntwo</p>", to_html("one\ntwo", options)?);
+
+        let options = Options {
+            block_tags: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            "<em>one</em><br />\ntwo\n\nthree<h2>four</h2><p>five</p>",
+            to_html("_one_\ntwo\n\nthree\n\nh2. four\n\np. five", options)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn raw_blocks() -> Result<()> {
+        let options = Options {
+            raw_blocks: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            "<div>\n*kept* \"as is\"\n</div><p><strong>marked</strong> up</p>",
+            to_html("<div>\n*kept* \"as is\"\n</div>\n\n*marked* up", options)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn align_classes() -> Result<()> {
+        let options = Options {
+            align_classes: Some(true),
+            ..Default::default()
+        };
+        let html = to_html("p>. a !<(x)/i.png!\n\n|^=. b|", options)?;
+        assert_eq!(
+            "<p class=\"align-right\">a <img alt=\"\" class=\"x align-left\" src=\"/i.png\" /></p><table><tr><td class=\"align-center align-top\">b</td></tr></table>",
+            html
+        );
+        assert!(!html.contains("style="));
+        Ok(())
+    }
+
+    #[test]
+    fn link_rel() -> Result<()> {
+        let options = Options {
+            link_rel: Some(LinkRel::Value("nofollow noopener".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p><a href=\"/a\" rel=\"nofollow noopener\">a</a></p>",
+            to_html("\"a\":/a", options)?
+        );
+
+        let external = |href: &str| href.starts_with("http").then(|| "external".to_string());
+        let options = Options {
+            link_rel: Some(LinkRel::Callback(Box::new(external))),
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p><a href=\"/a\">a</a> <a href=\"https://e.org/\" rel=\"external\">b</a></p>",
+            to_html("\"a\":/a \"b\":https://e.org/", options)?
+        );
+
+        let options = Options {
+            link_rel: Some(LinkRel::Callback(Box::new(external))),
+            restricted_mode: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p><a href=\"/a\" rel=\"nofollow\">a</a> <a href=\"https://e.org/\" rel=\"external nofollow\">b</a></p>",
+            to_html("\"a\":/a \"b\":https://e.org/", options)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn document_types() -> Result<()> {
+        let textile = "p[fr]<. a\nb !>i.png! W3C(World Wide Web Consortium)";
+        assert_eq!(
+            "<p lang=\"fr\" style=\"text-align:left;\" xml:lang=\"fr\">a<br />\nb <img align=\"right\" alt=\"\" src=\"i.png\" /> <acronym title=\"World Wide Web Consortium\">W3C</acronym></p>",
+            to_html(textile, Options::default())?
+        );
+
+        let options = Options {
+            document_type: Doctype::Html5,
+            ..Default::default()
+        };
+        assert_eq!(
+            "<p class=\"align-left\" lang=\"fr\">a<br>\nb <img alt=\"\" class=\"align-right\" src=\"i.png\"> <abbr title=\"World Wide Web Consortium\">W3C</abbr></p>",
+            to_html(textile, options)?
+        );
+        Ok(())
+    }
+}
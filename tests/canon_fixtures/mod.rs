@@ -52,7 +52,7 @@ impl Iterator for Fixtures {
                 Some((name, test_case)) => {
                     return Some(Fixture {
                         path: self.current_path.clone().unwrap(),
-                        name: name,
+                        name,
                         test_case,
                     })
                 }
@@ -76,7 +76,7 @@ pub struct FixtureSet {
 }
 
 #[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize)]
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::enum_variant_names)]
 pub enum FixtureSetup {
     setRestricted,
     setLite,
@@ -133,7 +133,7 @@ impl Iterator for FixturesRoot {
                     serde_yaml::from_str(&contents).unwrap();
                 Some(Self::Item {
                     cases: fixture_test_cases,
-                    path: path,
+                    path,
                 })
             }
         }
@@ -10,6 +10,7 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use unikko::{LinkRel, Options};
 
 fn normalized(fragment: &str) -> String {
     static REMOVE_WHITESPACE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s+$").unwrap());
@@ -27,24 +28,49 @@ fn normalized(fragment: &str) -> String {
         },
     )
     .unwrap();
-    return html;
+    html
+}
+
+/// Options matching the fixture's setup
+fn options(fixture: &Fixture) -> Options {
+    let mut options = Options::default();
+    for settings in fixture.test_case.setup.iter().flatten() {
+        for (setting, value) in settings {
+            let enabled = matches!(value.as_str(), "true" | "1");
+            match setting {
+                FixtureSetup::setRestricted => options.restricted_mode = enabled,
+                FixtureSetup::setLite => options.lite_mode = enabled,
+                FixtureSetup::setImages => options.handle_images = Some(enabled),
+                FixtureSetup::setDimensionlessImages => options.dimensionless_images = enabled,
+                FixtureSetup::setBlockTags => options.block_tags = enabled,
+                FixtureSetup::setLineWrap => options.line_wrap = enabled,
+                FixtureSetup::setRawBlocks => options.raw_blocks = enabled,
+                FixtureSetup::setImagePrefix => options.image_prefix = Some(value.clone()),
+                FixtureSetup::setLinkPrefix => options.link_prefix = Some(value.clone()),
+                FixtureSetup::setLinkRelationShip => {
+                    options.link_rel = Some(LinkRel::Value(value.clone()))
+                }
+            }
+        }
+    }
+    options
+}
+
+fn convert(fixture: &Fixture) -> Result<String, unikko::Error> {
+    let options = options(fixture);
+    unikko::textile_to_html_with_options(fixture.test_case.input.as_str(), options)
 }
 
 #[test]
 fn textile_to_html() {
     let fixtures = Fixtures::new();
     let mut passed: Vec<Fixture> = vec![];
-    let mut skipped: Vec<Fixture> = vec![];
     let mut errored: Vec<Fixture> = vec![];
     let mut mismatched: Vec<Fixture> = vec![];
 
     for fixture in fixtures {
-        if fixture.test_case.setup.is_some() {
-            skipped.push(fixture);
-            continue;
-        }
-        let actual = unikko::textile_to_html(fixture.test_case.input.as_str());
-        if matches!(actual, Err(_)) {
+        let actual = convert(&fixture);
+        if actual.is_err() {
             errored.push(fixture);
             continue;
         }
@@ -56,11 +82,11 @@ fn textile_to_html() {
         passed.push(fixture);
     }
 
-    if errored.len() > 0 || mismatched.len() > 0 {
+    if !errored.is_empty() || !mismatched.is_empty() {
         let mut error_examples: HashMap<PathBuf, (&Fixture, String)> = HashMap::new();
         for fixture in &errored {
             // Re-run to capture the error message
-            if let Err(e) = unikko::textile_to_html(fixture.test_case.input.as_str()) {
+            if let Err(e) = convert(fixture) {
                 error_examples
                     .entry(fixture.path.clone())
                     .or_insert((fixture, e.to_string()));
@@ -93,7 +119,7 @@ fn textile_to_html() {
                 println!("Expected:\n{}", fixture.test_case.expect);
 
                 // Re-compute actual for this example (raw HTML, not normalized)
-                let actual = unikko::textile_to_html(fixture.test_case.input.as_str()).unwrap();
+                let actual = convert(fixture).unwrap();
                 println!("Actual:\n{}", actual);
                 println!("{}", "=".repeat(80));
             }
@@ -101,7 +127,6 @@ fn textile_to_html() {
 
         println!("Summary");
         println!("Passed: {}", passed.len());
-        println!("Skipped: {}", skipped.len());
         println!("Errored: {}", errored.len());
         println!("Mismatched: {}", mismatched.len());
         panic!()